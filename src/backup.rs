@@ -0,0 +1,230 @@
+//! Backups of the whole database, statuses and history alike.
+//!
+//! A backup starts with a header line naming the format version, e.g.
+//! `git-status-tracker backup 1`, followed by a JSON document with the
+//! `statuses` and the `history` entries, each with the `time` in
+//! microseconds it was recorded at. Restoring reads any version up to the
+//! current one, so older backups stay usable as the schema changes.
+//!
+//! Backups can also be taken as statuses are put, into `backups/` in the
+//! database directory, named by the time they were taken to the
+//! microsecond.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::Entry;
+use crate::store::Transaction;
+use crate::{clock, xdg, Database, Status};
+
+/// When to take backups as statuses are put, from the config.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rotation {
+    /// Take one after this many puts.
+    pub every: Option<u64>,
+    /// Take one when the last is older than this, like 1d.
+    pub interval: Option<String>,
+    /// How many to keep, removing the oldest, at least the one just taken.
+    pub keep: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Rotation {
+        Rotation {
+            every: None,
+            interval: None,
+            keep: 5,
+        }
+    }
+}
+
+/// Backups taken into dir as statuses are put.
+#[derive(Clone, Debug)]
+pub struct Auto {
+    pub dir: PathBuf,
+    pub every: Option<u64>,
+    /// In seconds.
+    pub interval: Option<u64>,
+    pub keep: usize,
+}
+
+impl Auto {
+    /// The backups of rotation, taken into the backups directory of db.
+    pub fn new(rotation: &Rotation, db: &Path) -> Result<Auto, Box<dyn Error>> {
+        if rotation.every.is_none() && rotation.interval.is_none() {
+            return Err("backups in config need every or interval".into());
+        }
+        if rotation.keep == 0 {
+            return Err("backups in config need to keep at least 1".into());
+        }
+        let interval = rotation
+            .interval
+            .as_deref()
+            .map(clock::parse_duration)
+            .transpose()
+            .map_err(|e| format!("invalid backups interval in config: {}", e))?;
+        Ok(Auto {
+            dir: db.join("backups"),
+            every: rotation.every,
+            interval,
+            keep: rotation.keep,
+        })
+    }
+}
+
+/// The meta key of the puts since the last backup and when it was taken.
+const PROGRESS: &[u8] = b"backup";
+
+#[derive(Default, Serialize, Deserialize)]
+struct Progress {
+    writes: u64,
+    at: u64,
+}
+
+/// The backups taken into dir, oldest first.
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "backup") {
+            backups.push(path);
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// The first word of every backup.
+const MAGIC: &str = "git-status-tracker backup";
+
+/// The version written to new backups.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub time: u64,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub statuses: Vec<Status>,
+    pub history: Vec<Record>,
+}
+
+impl Backup {
+    pub fn to_string(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!(
+            "{} {}\n{}\n",
+            MAGIC,
+            VERSION,
+            serde_json::to_string_pretty(self)?
+        ))
+    }
+
+    pub fn from_str(input: &str) -> Result<Backup, Box<dyn Error>> {
+        let (header, body) = input.split_once('\n').unwrap_or((input, ""));
+        let version = header
+            .strip_prefix(MAGIC)
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .ok_or("not a backup: missing header")?;
+        if version > VERSION {
+            return Err(format!(
+                "backup version {} is newer than the supported version {}",
+                version, VERSION
+            )
+            .into());
+        }
+        Ok(serde_json::from_str(body)?)
+    }
+}
+
+impl Database {
+    /// Copy everything in the database into a backup.
+    pub fn backup(&self) -> Result<Backup, Box<dyn Error>> {
+        let mut statuses = self.list()?;
+        statuses.sort_by(|x, y| x.path.cmp(&y.path));
+        let history = self
+            .all_history()?
+            .into_iter()
+            .map(|(time, entry)| Record { time, entry })
+            .collect();
+        Ok(Backup { statuses, history })
+    }
+
+    /// Count a put and take a backup if one is due, removing the oldest
+    /// beyond what's kept.
+    pub fn rotate(&self) -> Result<(), Box<dyn Error>> {
+        let auto = match &self.backups {
+            Some(auto) => auto,
+            None => return Ok(()),
+        };
+        let mut progress: Progress = match self.meta.get(PROGRESS)? {
+            Some(progress) => serde_json::from_slice(&progress)?,
+            None => Progress::default(),
+        };
+        progress.writes += 1;
+        let now = clock::now();
+        let due = auto.every.is_some_and(|every| progress.writes >= every)
+            || auto
+                .interval
+                .is_some_and(|interval| now >= progress.at + interval);
+        if due {
+            xdg::create_private(&auto.dir)?;
+            let backup = self.backup()?.to_string()?;
+            // Each backup gets its own name, even when two are taken in the
+            // same microsecond, so neither overwrites the other.
+            let mut micros = clock::now_micros();
+            let mut file = loop {
+                let name = format!("{}.backup", clock::format_micros(micros));
+                match fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(auto.dir.join(name))
+                {
+                    Ok(file) => break file,
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => micros += 1,
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            file.write_all(backup.as_bytes())?;
+            let backups = list(&auto.dir)?;
+            for old in &backups[..backups.len().saturating_sub(auto.keep)] {
+                fs::remove_file(old)?;
+            }
+            progress = Progress { writes: 0, at: now };
+        }
+        self.meta.put(PROGRESS, serde_json::to_vec(&progress)?)
+    }
+
+    /// Load a backup, replacing everything in the database unless merge is
+    /// set, in which case the backup is only added on top.
+    pub fn restore(&self, backup: Backup, merge: bool) -> Result<(), Box<dyn Error>> {
+        let mut transaction = Transaction::default();
+        if !merge {
+            self.clear_statuses(&mut transaction)?;
+            self.clear_history(&mut transaction)?;
+        }
+        self.stage(&mut transaction, &backup.statuses)?;
+        let history = backup
+            .history
+            .into_iter()
+            .map(|r| (r.time, r.entry))
+            .collect::<Vec<_>>();
+        self.insert_history(&mut transaction, &history)?;
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok(())
+    }
+}
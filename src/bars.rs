@@ -0,0 +1,547 @@
+//! Output in the formats status bars read directly.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Level;
+use crate::icons::Icons;
+use crate::stats::{Stats, SummaryStyle};
+use crate::{template, Status};
+
+/// The counts of each category with any changes, one per line, e.g.
+/// `modified: 2`.
+fn breakdown(statuses: &[Status]) -> Vec<String> {
+    template::CATEGORIES
+        .iter()
+        .filter_map(|category| {
+            let count = statuses
+                .iter()
+                .filter_map(|s| template::category(s, category))
+                .sum::<u64>();
+            (count > 0).then(|| format!("{}: {}", category, count))
+        })
+        .collect()
+}
+
+/// The branch line followed by the number of changes, if any.
+fn label(status: &Status) -> String {
+    let mut text = status.branch_line();
+    let changes = status.changes();
+    if changes > 0 {
+        text.push_str(&format!(" {}", changes));
+    }
+    text
+}
+
+fn class(changes: u64) -> &'static str {
+    if changes > 0 {
+        "dirty"
+    } else {
+        "clean"
+    }
+}
+
+/// The JSON a waybar custom module with `"return-type": "json"` reads.
+#[derive(Serialize)]
+struct Waybar {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+    percentage: u64,
+}
+
+impl Waybar {
+    fn to_string(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// A waybar module for status. The percentage is how close the changes are
+/// to threshold.
+pub fn waybar(status: &Status, threshold: u64) -> Result<String, Box<dyn Error>> {
+    let changes = status.changes();
+    let text = label(status);
+    let mut tooltip = vec![status.path.clone()];
+    tooltip.extend(breakdown(std::slice::from_ref(status)));
+    Waybar {
+        text,
+        tooltip: tooltip.join("\n"),
+        class: class(changes),
+        percentage: (changes * 100 / threshold.max(1)).min(100),
+    }
+    .to_string()
+}
+
+/// A waybar module summarizing statuses. The percentage is the share of
+/// repositories that are dirty.
+pub fn waybar_summary(statuses: &[Status], style: &SummaryStyle) -> Result<String, Box<dyn Error>> {
+    let stats = Stats::new(statuses);
+    let mut tooltip = breakdown(statuses);
+    let mut dirty = statuses.iter().filter(|s| s.is_dirty()).collect::<Vec<_>>();
+    dirty.sort_by(|x, y| x.path.cmp(&y.path));
+    tooltip.extend(
+        dirty
+            .iter()
+            .map(|s| format!("{}: {} changes", s.path, s.changes())),
+    );
+    Waybar {
+        text: stats.summary(style),
+        tooltip: tooltip.join("\n"),
+        class: class(stats.changes),
+        percentage: (stats.dirty * 100)
+            .checked_div(stats.repositories)
+            .unwrap_or(0),
+    }
+    .to_string()
+}
+
+/// The hex colors bars show statuses in.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub clean: String,
+    pub few: String,
+    pub many: String,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            clean: "#98c379".to_string(),
+            few: "#e5c07b".to_string(),
+            many: "#e06c75".to_string(),
+        }
+    }
+}
+
+impl Colors {
+    fn pick(&self, changes: u64, threshold: u64) -> &str {
+        match Level::new(changes, threshold) {
+            Level::Clean => &self.clean,
+            Level::Few => &self.few,
+            Level::Many => &self.many,
+        }
+    }
+}
+
+/// The polybar font with the icon glyphs.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Polybar {
+    /// The 1-based index of the font.
+    pub font: u32,
+}
+
+impl Default for Polybar {
+    fn default() -> Polybar {
+        Polybar { font: 2 }
+    }
+}
+
+impl Polybar {
+    fn icon(&self, icon: &str) -> String {
+        format!("%{{T{}}}{}%{{T-}}", self.font, icon)
+    }
+
+    /// Escape the text so polybar doesn't read format tags in it.
+    fn escape(text: &str) -> String {
+        text.replace('%', "%%")
+    }
+}
+
+/// A polybar `custom/script` line for status, with the icons in the icon
+/// font and everything colored by how dirty it is.
+pub fn polybar(
+    status: &Status,
+    icons: &Icons,
+    polybar: &Polybar,
+    colors: &Colors,
+    threshold: u64,
+) -> String {
+    let mut segments = Vec::new();
+    if !status.branch.is_empty() {
+        segments.push(format!(
+            "{} {}",
+            polybar.icon(&icons.branch),
+            Polybar::escape(&status.branch_line())
+        ));
+    }
+    for (code, count) in &status.git_status {
+        let icon = icons.codes.get(code).unwrap_or(code);
+        segments.push(format!("{}{}", polybar.icon(icon), count));
+    }
+    format!(
+        "%{{F{}}}{}%{{F-}}",
+        colors.pick(status.changes(), threshold),
+        segments.join(" ")
+    )
+}
+
+/// A polybar line summarizing statuses.
+pub fn polybar_summary(
+    statuses: &[Status],
+    style: &SummaryStyle,
+    colors: &Colors,
+    threshold: u64,
+) -> String {
+    let stats = Stats::new(statuses);
+    format!(
+        "%{{F{}}}{}%{{F-}}",
+        colors.pick(stats.changes, threshold),
+        Polybar::escape(&stats.summary(style))
+    )
+}
+
+/// The full text, short text and color lines i3blocks reads.
+pub fn i3blocks(status: &Status, colors: &Colors, threshold: u64) -> String {
+    let changes = status.changes();
+    let mut full = vec![status.branch_line()];
+    full.extend(
+        status
+            .git_status
+            .iter()
+            .map(|(code, count)| format!("{}{}", count, code)),
+    );
+    let mut short = status.branch.clone();
+    if changes > 0 {
+        short.push_str(&format!(" {}", changes));
+    }
+    format!(
+        "{}\n{}\n{}",
+        full.join(" "),
+        short,
+        colors.pick(changes, threshold)
+    )
+}
+
+/// The i3blocks lines summarizing statuses.
+pub fn i3blocks_summary(
+    statuses: &[Status],
+    style: &SummaryStyle,
+    colors: &Colors,
+    threshold: u64,
+) -> String {
+    let stats = Stats::new(statuses);
+    let short = SummaryStyle {
+        compact: true,
+        separator: "/",
+        ..*style
+    };
+    format!(
+        "{}\n{}\n{}",
+        stats.summary(style),
+        stats.summary(&short),
+        colors.pick(stats.changes, threshold)
+    )
+}
+
+/// Escape the text so tmux doesn't read styles or formats in it.
+fn tmux_escape(text: &str) -> String {
+    text.replace('#', "##")
+}
+
+/// Shorten text to at most width characters, ending it with … when cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short = text
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    short.push('…');
+    short
+}
+
+/// A tmux status line segment for status, colored by how dirty it is and
+/// with the branch cut to max_branch characters if given.
+pub fn tmux(status: &Status, colors: &Colors, threshold: u64, max_branch: Option<usize>) -> String {
+    let mut status = status.clone();
+    if let Some(width) = max_branch {
+        status.branch = truncate(&status.branch, width);
+    }
+    let changes = status.changes();
+    let text = label(&status);
+    format!(
+        "#[fg={}]{}#[default]",
+        colors.pick(changes, threshold),
+        tmux_escape(&text)
+    )
+}
+
+/// A tmux status line segment summarizing statuses.
+pub fn tmux_summary(
+    statuses: &[Status],
+    style: &SummaryStyle,
+    colors: &Colors,
+    threshold: u64,
+) -> String {
+    let stats = Stats::new(statuses);
+    format!(
+        "#[fg={}]{}#[default]",
+        colors.pick(stats.changes, threshold),
+        tmux_escape(&stats.summary(style))
+    )
+}
+
+/// The symbols starship's git_status module uses for each category.
+const STARSHIP: &[(&str, &str)] = &[
+    ("conflicted", "="),
+    ("deleted", "✘"),
+    ("renamed", "»"),
+    ("modified", "!"),
+    ("added", "+"),
+    ("untracked", "?"),
+];
+
+/// The output for a starship custom module: the branch followed by
+/// compact counts, e.g. `main ⇡1 $2 !3 ?1`.
+pub fn starship(status: &Status) -> String {
+    let mut segments = vec![status.branch.clone()];
+    let mut push = |symbol: &str, count: u64| {
+        if count > 0 {
+            segments.push(format!("{}{}", symbol, count));
+        }
+    };
+    push("⇡", status.ahead);
+    push("⇣", status.behind);
+    push("$", status.stashes);
+    for (category, symbol) in STARSHIP {
+        push(symbol, template::category(status, category).unwrap_or(0));
+    }
+    segments.retain(|s| !s.is_empty());
+    segments.join(" ")
+}
+
+/// The icon xmobar shows before statuses.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Xmobar {
+    /// The path of an XBM or XPM file, if any.
+    pub icon: Option<String>,
+}
+
+impl Xmobar {
+    /// Wrap text in markup coloring it, after the icon if there is one. The
+    /// text is raw so xmobar doesn't read tags in it, and raw's length is in
+    /// characters.
+    fn markup(&self, color: &str, text: &str) -> String {
+        let icon = match &self.icon {
+            Some(icon) => format!("<icon={}/> ", icon),
+            None => String::new(),
+        };
+        format!(
+            "<fc={}>{}<raw={}:{}/></fc>",
+            color,
+            icon,
+            text.chars().count(),
+            text
+        )
+    }
+}
+
+/// The xmobar markup for status, colored by how dirty it is.
+pub fn xmobar(status: &Status, xmobar: &Xmobar, colors: &Colors, threshold: u64) -> String {
+    let changes = status.changes();
+    let text = label(status);
+    xmobar.markup(colors.pick(changes, threshold), &text)
+}
+
+/// The xmobar markup summarizing statuses.
+pub fn xmobar_summary(
+    statuses: &[Status],
+    style: &SummaryStyle,
+    xmobar: &Xmobar,
+    colors: &Colors,
+    threshold: u64,
+) -> String {
+    let stats = Stats::new(statuses);
+    xmobar.markup(colors.pick(stats.changes, threshold), &stats.summary(style))
+}
+
+/// What an eww widget shows for a status.
+#[derive(Serialize)]
+struct Eww {
+    text: String,
+    class: &'static str,
+    changes: u64,
+}
+
+/// A JSON object keyed by path for eww's deflisten or defpoll, on one line.
+pub fn eww(statuses: &[Status]) -> Result<String, Box<dyn Error>> {
+    let widgets = statuses
+        .iter()
+        .map(|s| {
+            let eww = Eww {
+                text: label(s),
+                class: class(s.changes()),
+                changes: s.changes(),
+            };
+            (s.path.as_str(), eww)
+        })
+        .collect::<BTreeMap<_, _>>();
+    Ok(serde_json::to_string(&widgets)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(branch: &str, codes: &[(&str, u64)]) -> Status {
+        let mut status = Status {
+            path: "/repo".to_string(),
+            branch: branch.to_string(),
+            ..Default::default()
+        };
+        status.set_counts(
+            codes
+                .iter()
+                .map(|(code, count)| (code.to_string(), *count))
+                .collect(),
+        );
+        status
+    }
+
+    const STYLE: SummaryStyle = SummaryStyle {
+        icon: "🌿",
+        separator: " · ",
+        compact: false,
+    };
+
+    #[test]
+    fn xmobar_counts_characters() {
+        let mut status = status("main", &[("M", 4)]);
+        status.ahead = 2;
+        status.stashes = 1;
+        let colors = Colors::default();
+        assert_eq!(
+            xmobar(&status, &Xmobar::default(), &colors, 10),
+            "<fc=#e5c07b><raw=12:main ⇡2 *1 4/></fc>"
+        );
+        let icon = Xmobar {
+            icon: Some("git.xpm".to_string()),
+        };
+        let statuses = std::slice::from_ref(&status);
+        assert_eq!(
+            xmobar_summary(statuses, &STYLE, &icon, &colors, 10),
+            "<fc=#e5c07b><icon=git.xpm/> <raw=31:🌿 1 repos · 1 dirty · 4 changes/></fc>"
+        );
+    }
+
+    #[test]
+    fn waybar_modules() {
+        let dirty = status("main", &[("M", 2), ("??", 1)]);
+        assert_eq!(
+            waybar(&dirty, 10).unwrap(),
+            r#"{"text":"main 3","tooltip":"/repo\nmodified: 2\nuntracked: 1","class":"dirty","percentage":30}"#
+        );
+        assert_eq!(
+            waybar(&dirty, 2).unwrap(),
+            r#"{"text":"main 3","tooltip":"/repo\nmodified: 2\nuntracked: 1","class":"dirty","percentage":100}"#
+        );
+        let mut clean = status("dev", &[]);
+        clean.path = "/clean".to_string();
+        assert_eq!(
+            waybar_summary(&[dirty, clean], &STYLE).unwrap(),
+            r#"{"text":"🌿 2 repos · 1 dirty · 3 changes","tooltip":"modified: 2\nuntracked: 1\n/repo: 3 changes","class":"dirty","percentage":50}"#
+        );
+        assert_eq!(
+            waybar_summary(&[], &STYLE).unwrap(),
+            r#"{"text":"🌿 0 repos · 0 dirty · 0 changes","tooltip":"","class":"clean","percentage":0}"#
+        );
+    }
+
+    #[test]
+    fn polybar_lines() {
+        let dirty = status("50%", &[("M", 2), ("??", 1)]);
+        let (icons, colors) = (Icons::default(), Colors::default());
+        assert_eq!(
+            polybar(&dirty, &icons, &Polybar::default(), &colors, 3),
+            "%{F#e06c75}%{T2}\u{e0a0}%{T-} 50%% %{T2}\u{f128}%{T-}1 %{T2}\u{f040}%{T-}2%{F-}"
+        );
+        let font: Polybar = serde_json::from_str(r#"{"font": 3}"#).unwrap();
+        assert_eq!(
+            polybar(&status("main", &[]), &icons, &font, &colors, 3),
+            "%{F#98c379}%{T3}\u{e0a0}%{T-} main%{F-}"
+        );
+        assert_eq!(
+            polybar_summary(std::slice::from_ref(&dirty), &STYLE, &colors, 10),
+            "%{F#e5c07b}🌿 1 repos · 1 dirty · 3 changes%{F-}"
+        );
+    }
+
+    #[test]
+    fn i3blocks_lines() {
+        let mut dirty = status("main", &[("M", 2), ("??", 1)]);
+        dirty.ahead = 1;
+        let colors = Colors::default();
+        assert_eq!(
+            i3blocks(&dirty, &colors, 10),
+            "main ⇡1 1?? 2M\nmain 3\n#e5c07b"
+        );
+        assert_eq!(
+            i3blocks(&status("main", &[]), &colors, 10),
+            "main\nmain\n#98c379"
+        );
+        assert_eq!(
+            i3blocks_summary(&[dirty], &STYLE, &colors, 3),
+            "🌿 1 repos · 1 dirty · 3 changes\n🌿 1/1/3\n#e06c75"
+        );
+    }
+
+    #[test]
+    fn tmux_segments() {
+        let colors = Colors::default();
+        let dirty = status("a#b", &[("M", 2), ("??", 1)]);
+        assert_eq!(
+            tmux(&dirty, &colors, 10, None),
+            "#[fg=#e5c07b]a##b 3#[default]"
+        );
+        let long = status("feature/long", &[]);
+        assert_eq!(
+            tmux(&long, &colors, 10, Some(8)),
+            "#[fg=#98c379]feature…#[default]"
+        );
+        assert_eq!(
+            tmux(&long, &colors, 10, Some(12)),
+            "#[fg=#98c379]feature/long#[default]"
+        );
+        assert_eq!(
+            tmux_summary(&[dirty, long], &STYLE, &colors, 3),
+            "#[fg=#e06c75]🌿 2 repos · 1 dirty · 3 changes#[default]"
+        );
+    }
+
+    #[test]
+    fn truncation_counts_characters() {
+        assert_eq!(truncate("ünïcödé", 4), "ünï…");
+        assert_eq!(truncate("main", 4), "main");
+        assert_eq!(truncate("main", 0), "…");
+    }
+
+    #[test]
+    fn starship_counts() {
+        let mut dirty = status("main", &[("M", 3), ("??", 1), ("A", 1), ("D", 2), ("U", 1)]);
+        dirty.ahead = 1;
+        dirty.stashes = 2;
+        assert_eq!(starship(&dirty), "main ⇡1 $2 =1 ✘2 !3 +1 ?1");
+        assert_eq!(starship(&status("main", &[])), "main");
+        let mut behind = status("", &[]);
+        behind.behind = 4;
+        assert_eq!(starship(&behind), "⇣4");
+    }
+
+    #[test]
+    fn eww_widgets() {
+        let dirty = status("main", &[("M", 2)]);
+        let mut clean = status("dev", &[]);
+        clean.path = "/clean".to_string();
+        assert_eq!(
+            eww(&[dirty, clean]).unwrap(),
+            r#"{"/clean":{"text":"dev","class":"clean","changes":0},"/repo":{"text":"main 2","class":"dirty","changes":2}}"#
+        );
+        assert_eq!(eww(&[]).unwrap(), "{}");
+    }
+}
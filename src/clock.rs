@@ -0,0 +1,167 @@
+//! Timestamps, stored as seconds since the unix epoch.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The current time in seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The current time in microseconds since the unix epoch.
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// Format a timestamp as an RFC 3339 UTC date, e.g. `2022-02-20T17:04:05Z`.
+pub fn format(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Convert days since the epoch to a civil date. See
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Format a timestamp in microseconds like format, with the fraction of the
+/// second, e.g. `2022-02-20T17:04:05.012345Z`.
+pub fn format_micros(micros: u64) -> String {
+    let seconds = format(micros / 1_000_000);
+    format!(
+        "{}.{:06}Z",
+        seconds.trim_end_matches('Z'),
+        micros % 1_000_000
+    )
+}
+
+/// Describe how long ago a timestamp was, e.g. `5m ago`.
+pub fn ago(timestamp: u64) -> String {
+    let seconds = now().saturating_sub(timestamp);
+    let (count, unit) = match seconds {
+        s if s < 60 => (s, "s"),
+        s if s < 60 * 60 => (s / 60, "m"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "h"),
+        s if s < 7 * 24 * 60 * 60 => (s / (24 * 60 * 60), "d"),
+        s => (s / (7 * 24 * 60 * 60), "w"),
+    };
+    format!("{}{} ago", count, unit)
+}
+
+/// Parse a point in time given as seconds since the epoch, an RFC 3339 UTC
+/// date like `2022-02-20T17:04:05Z`, or a duration before now like `2h`.
+pub fn parse(s: &str) -> Result<u64, String> {
+    if let Ok(timestamp) = s.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    if let Ok(duration) = parse_duration(s) {
+        return Ok(now().saturating_sub(duration));
+    }
+    parse_date(s).ok_or_else(|| format!("invalid time {}", s))
+}
+
+/// Parse a duration in seconds like `30s`, `5m`, `2h`, `7d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration {}", s))?;
+    let unit = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid duration {}", s)),
+    };
+    number
+        .checked_mul(unit)
+        .ok_or_else(|| format!("duration {} is too long", s))
+}
+
+fn parse_date(s: &str) -> Option<u64> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    // RFC 3339 years have four digits.
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // The inverse of format. See
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30"), Ok(30));
+        assert_eq!(parse_duration("5m"), Ok(300));
+        assert_eq!(parse_duration("2w"), Ok(2 * 7 * 24 * 60 * 60));
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn long_durations_are_errors() {
+        assert_eq!(
+            parse_duration("99999999999999999w"),
+            Err("duration 99999999999999999w is too long".to_string())
+        );
+        assert!(parse_duration("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        assert_eq!(parse("2022-02-20T17:04:05Z"), Ok(1645376645));
+        assert_eq!(format(1645376645), "2022-02-20T17:04:05Z");
+        assert_eq!(
+            format_micros(1645376645012345),
+            "2022-02-20T17:04:05.012345Z"
+        );
+        assert!(parse("99999999999999999-01-01T00:00:00Z").is_err());
+    }
+}
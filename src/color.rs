@@ -0,0 +1,147 @@
+//! ANSI colors for statuses: one color when clean, another for a few
+//! changes and a third for many.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use clap::ArgEnum;
+use serde::Deserialize;
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum When {
+    Auto,
+    Always,
+    Never,
+}
+
+/// The colors and the number of changes at which a status has many.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub clean: String,
+    pub few: String,
+    pub many: String,
+    /// The color conflicts are shown in, whatever the other changes.
+    pub conflicted: String,
+    pub threshold: u64,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            clean: "green".to_string(),
+            few: "yellow".to_string(),
+            many: "red".to_string(),
+            conflicted: "1;31".to_string(),
+            threshold: 10,
+        }
+    }
+}
+
+/// How dirty a status is.
+pub enum Level {
+    Clean,
+    Few,
+    Many,
+}
+
+impl Level {
+    pub fn new(changes: u64, threshold: u64) -> Level {
+        match changes {
+            0 => Level::Clean,
+            n if n < threshold => Level::Few,
+            _ => Level::Many,
+        }
+    }
+}
+
+/// The SGR parameters for a color name, or the name itself so raw codes
+/// like `1;35` work too.
+fn sgr(color: &str) -> &str {
+    match color {
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        color => color,
+    }
+}
+
+/// Paints text in the color for a number of changes, or leaves it alone when
+/// color is off.
+#[derive(Debug)]
+pub struct Palette {
+    enabled: bool,
+    colors: Colors,
+}
+
+impl Palette {
+    pub fn new(when: When, colors: Colors) -> Palette {
+        let enabled = match when {
+            When::Always => true,
+            When::Never => false,
+            When::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        };
+        Palette { enabled, colors }
+    }
+
+    /// The number of changes at which a status has many.
+    pub fn threshold(&self) -> u64 {
+        self.colors.threshold
+    }
+
+    pub fn paint(&self, changes: u64, text: &str) -> String {
+        if !self.enabled || text.is_empty() {
+            return text.to_string();
+        }
+        let color = match Level::new(changes, self.colors.threshold) {
+            Level::Clean => &self.colors.clean,
+            Level::Few => &self.colors.few,
+            Level::Many => &self.colors.many,
+        };
+        format!("\x1b[{}m{}\x1b[0m", sgr(color), text)
+    }
+
+    /// Paint text in the color for conflicts.
+    pub fn conflicted(&self, text: &str) -> String {
+        if !self.enabled || text.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", sgr(&self.colors.conflicted), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn painted_by_changes() {
+        let palette = Palette::new(When::Always, Colors::default());
+        assert_eq!(palette.paint(0, "main"), "\x1b[32mmain\x1b[0m");
+        assert_eq!(palette.paint(9, "main"), "\x1b[33mmain\x1b[0m");
+        assert_eq!(palette.paint(10, "main"), "\x1b[31mmain\x1b[0m");
+        assert_eq!(palette.paint(3, ""), "");
+        assert_eq!(palette.conflicted("UU"), "\x1b[1;31mUU\x1b[0m");
+    }
+
+    #[test]
+    fn raw_codes_and_thresholds() {
+        let colors: Colors = serde_json::from_str(r#"{"few": "1;35", "threshold": 3}"#).unwrap();
+        let palette = Palette::new(When::Always, colors);
+        assert_eq!(palette.threshold(), 3);
+        assert_eq!(palette.paint(2, "x"), "\x1b[1;35mx\x1b[0m");
+        assert_eq!(palette.paint(3, "x"), "\x1b[31mx\x1b[0m");
+    }
+
+    #[test]
+    fn never_paints() {
+        let palette = Palette::new(When::Never, Colors::default());
+        assert_eq!(palette.paint(10, "main"), "main");
+        assert_eq!(palette.conflicted("UU"), "UU");
+    }
+}
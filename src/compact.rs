@@ -0,0 +1,154 @@
+//! Rewriting the database to reclaim space.
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{xdg, Database};
+
+/// Files and directories sled keeps in the database directory.
+pub fn is_sled_file(name: &str) -> bool {
+    matches!(name, "conf" | "db" | "blobs") || name.starts_with("snap.")
+}
+
+/// Format a size in bytes for people, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// The directory next to dir with suffix added to its name.
+fn sibling(dir: &Path, suffix: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let name = dir
+        .file_name()
+        .ok_or_else(|| format!("can't compact the database in {}", dir.display()))?;
+    Ok(dir.with_file_name(format!("{}.{}", name.to_string_lossy(), suffix)))
+}
+
+/// Swap the database in fresh in for the one in dir, moving everything in
+/// dir besides sled's files, like backups, over to it. The old database is
+/// only renamed aside until the new one is in place, so whichever rename
+/// fails, one of them is left whole.
+fn swap(
+    dir: &Path,
+    fresh: &Path,
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    let old = sibling(dir, "old")?;
+    rename(dir, &old)?;
+    if let Err(e) = rename(fresh, dir) {
+        rename(&old, dir)?;
+        return Err(format!("failed to move the compacted database in: {}", e).into());
+    }
+    for entry in fs::read_dir(&old)? {
+        let entry = entry?;
+        if !is_sled_file(&entry.file_name().to_string_lossy()) {
+            rename(&entry.path(), &dir.join(entry.file_name()))?;
+        }
+    }
+    fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+impl Database {
+    /// Copy the live entries of every tree into a fresh database and swap it
+    /// in for this one, which lives in dir. Returns the size on disk before
+    /// and after.
+    pub fn compact(self, dir: &Path) -> Result<(u64, u64), Box<dyn Error>> {
+        if self.read_only {
+            return Err("the database is open read-only".into());
+        }
+        let db = self
+            .backend
+            .sled()
+            .ok_or("only sled databases can be compacted")?;
+        let before = db.size_on_disk()?;
+        let old = sibling(dir, "old")?;
+        if old.exists() {
+            return Err(format!(
+                "{} is left from an earlier compaction; move it back to {} or remove it",
+                old.display(),
+                dir.display()
+            )
+            .into());
+        }
+        let fresh_dir = sibling(dir, "compact")?;
+        if fresh_dir.exists() {
+            fs::remove_dir_all(&fresh_dir)?;
+        }
+        xdg::create_private(&fresh_dir)?;
+        let fresh = sled::open(&fresh_dir)?;
+        fresh.import(db.export());
+        fresh.flush()?;
+        let after = fresh.size_on_disk()?;
+        drop(fresh);
+        drop(self);
+
+        swap(dir, &fresh_dir, |from, to| fs::rename(from, to))?;
+        Ok((before, after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database in a new directory with one key, and a fresh one beside it
+    /// with another.
+    fn databases(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!(
+            "git-status-tracker-compact-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let (dir, fresh) = (root.join("db"), root.join("db.compact"));
+        sled::open(&dir).unwrap().insert("old", "1").unwrap();
+        sled::open(&fresh).unwrap().insert("new", "1").unwrap();
+        fs::create_dir(dir.join("backups")).unwrap();
+        (dir, fresh)
+    }
+
+    #[test]
+    fn swap_keeps_other_files() {
+        let (dir, fresh) = databases("swap");
+        swap(&dir, &fresh, |from, to| fs::rename(from, to)).unwrap();
+        let db = sled::open(&dir).unwrap();
+        assert!(db.contains_key("new").unwrap());
+        assert!(dir.join("backups").is_dir());
+        assert!(!fresh.exists());
+        assert!(!sibling(&dir, "old").unwrap().exists());
+        drop(db);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failed_swap_keeps_the_old_database() {
+        let (dir, fresh) = databases("failed");
+        let moved = fresh.clone();
+        let rename = |from: &Path, to: &Path| {
+            if from == moved {
+                return Err(io::Error::other("no space left"));
+            }
+            fs::rename(from, to)
+        };
+        let e = swap(&dir, &fresh, rename).unwrap_err();
+        assert!(e.to_string().contains("no space left"), "{}", e);
+        let db = sled::open(&dir).unwrap();
+        assert!(db.contains_key("old").unwrap());
+        assert!(dir.join("backups").is_dir());
+        drop(db);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+//! Shell completion scripts generated from the `Cli` definition.
+
+use std::fmt::Write;
+
+use clap::{Arg, ArgEnum, Command, ValueHint};
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Generate the completion script for shell.
+pub fn generate(cmd: &Command, shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(cmd),
+        Shell::Zsh => zsh(cmd),
+        Shell::Fish => fish(cmd),
+    }
+}
+
+/// The subcommands and the arguments each of them accepts, including global
+/// arguments from the top-level command.
+fn subcommands<'a, 'help>(
+    cmd: &'a Command<'help>,
+) -> Vec<(&'a Command<'help>, Vec<&'a Arg<'help>>)> {
+    let globals = cmd
+        .get_arguments()
+        .filter(|a| a.is_global_set())
+        .collect::<Vec<_>>();
+    cmd.get_subcommands()
+        .map(|sub| {
+            let mut args = sub.get_arguments().collect::<Vec<_>>();
+            for global in &globals {
+                if !args.iter().any(|a| a.get_id() == global.get_id()) {
+                    args.push(global);
+                }
+            }
+            (sub, args)
+        })
+        .collect()
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("--{}", long));
+    }
+    flags
+}
+
+fn possible_values<'a>(arg: &'a Arg) -> Vec<&'a str> {
+    arg.get_possible_values()
+        .unwrap_or_default()
+        .iter()
+        .map(|v| v.get_name())
+        .collect()
+}
+
+fn about<'a>(cmd: &'a Command) -> &'a str {
+    cmd.get_about()
+        .and_then(|a| a.lines().next())
+        .unwrap_or_default()
+}
+
+fn help<'a>(arg: &'a Arg) -> &'a str {
+    arg.get_help()
+        .and_then(|h| h.lines().next())
+        .unwrap_or_default()
+}
+
+fn bash(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let subcommands = subcommands(cmd);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}() {{", function);
+    let _ = writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(out, "    if [[ $COMP_CWORD -eq 1 ]]; then");
+    let names = subcommands
+        .iter()
+        .map(|(sub, _)| sub.get_name())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        names
+    );
+    let _ = writeln!(out, "        return");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "    case \"${{COMP_WORDS[1]}}\" in");
+    for (sub, args) in &subcommands {
+        let _ = writeln!(out, "        {})", sub.get_name());
+        let _ = writeln!(out, "            case \"$prev\" in");
+        for arg in args
+            .iter()
+            .filter(|a| !a.is_positional() && a.is_takes_value_set())
+        {
+            let values = possible_values(arg);
+            let reply = match arg.get_value_hint() {
+                _ if !values.is_empty() => format!(
+                    "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                    values.join(" ")
+                ),
+                ValueHint::DirPath => "COMPREPLY=($(compgen -d -- \"$cur\"))".to_string(),
+                ValueHint::FilePath | ValueHint::AnyPath => {
+                    "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string()
+                }
+                _ => "COMPREPLY=()".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "                {}) {}; return ;;",
+                flags(arg).join("|"),
+                reply
+            );
+        }
+        let _ = writeln!(out, "            esac");
+        let mut words = args.iter().flat_map(|a| flags(a)).collect::<Vec<_>>();
+        for arg in args.iter().filter(|a| a.is_positional()) {
+            words.extend(possible_values(arg).iter().map(|v| v.to_string()));
+        }
+        let _ = writeln!(
+            out,
+            "            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            words.join(" ")
+        );
+        let _ = writeln!(out, "            ;;");
+    }
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out, "complete -F {} {}", function, name);
+    out
+}
+
+fn zsh_escape(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let subcommands = subcommands(cmd);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "#compdef {}", name);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_{}() {{", name);
+    let _ = writeln!(out, "    local line state");
+    let _ = writeln!(out, "    _arguments -C '1: :->command' '*:: :->args'");
+    let _ = writeln!(out, "    case $state in");
+    let _ = writeln!(out, "        command)");
+    let _ = writeln!(out, "            local -a commands");
+    let _ = writeln!(out, "            commands=(");
+    for (sub, _) in &subcommands {
+        let _ = writeln!(
+            out,
+            "                '{}:{}'",
+            sub.get_name(),
+            zsh_escape(about(sub))
+        );
+    }
+    let _ = writeln!(out, "            )");
+    let _ = writeln!(out, "            _describe 'command' commands");
+    let _ = writeln!(out, "            ;;");
+    let _ = writeln!(out, "        args)");
+    let _ = writeln!(out, "            case $line[1] in");
+    for (sub, args) in &subcommands {
+        let _ = writeln!(out, "                {})", sub.get_name());
+        let _ = write!(out, "                    _arguments");
+        for arg in args {
+            let values = possible_values(arg);
+            let action = match arg.get_value_hint() {
+                _ if !arg.is_takes_value_set() => String::new(),
+                _ if !values.is_empty() => format!(":{}:({})", arg.get_id(), values.join(" ")),
+                ValueHint::DirPath => format!(":{}:_files -/", arg.get_id()),
+                ValueHint::FilePath | ValueHint::AnyPath => format!(":{}:_files", arg.get_id()),
+                _ => format!(":{}: ", arg.get_id()),
+            };
+            if arg.is_positional() {
+                let _ = write!(out, " \\\n                        '{}'", action);
+                continue;
+            }
+            let flags = flags(arg);
+            let spec = if flags.len() > 1 {
+                format!("'({})'{{{}}}", flags.join(" "), flags.join(","))
+            } else {
+                flags.join("")
+            };
+            let _ = write!(
+                out,
+                " \\\n                        {}'[{}]{}'",
+                spec,
+                zsh_escape(help(arg)),
+                action
+            );
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "                    ;;");
+    }
+    let _ = writeln!(out, "            esac");
+    let _ = writeln!(out, "            ;;");
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_{} \"$@\"", name);
+    out
+}
+
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let subcommands = subcommands(cmd);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "complete -c {} -f", name);
+    for (sub, _) in &subcommands {
+        let _ = writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand' -a {} -d '{}'",
+            name,
+            sub.get_name(),
+            fish_escape(about(sub))
+        );
+    }
+    for (sub, args) in &subcommands {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.get_name());
+        for arg in args {
+            let values = possible_values(arg);
+            let mut line = format!("complete -c {} -n '{}'", name, condition);
+            if let Some(short) = arg.get_short() {
+                let _ = write!(line, " -s {}", short);
+            }
+            if let Some(long) = arg.get_long() {
+                let _ = write!(line, " -l {}", long);
+            }
+            if arg.is_takes_value_set() {
+                match arg.get_value_hint() {
+                    _ if !values.is_empty() => {
+                        let _ = write!(line, " -r -a '{}'", values.join(" "));
+                    }
+                    ValueHint::DirPath => line.push_str(" -r -a '(__fish_complete_directories)'"),
+                    ValueHint::FilePath | ValueHint::AnyPath => line.push_str(" -r -F"),
+                    _ => line.push_str(" -r"),
+                }
+            }
+            let _ = write!(line, " -d '{}'", fish_escape(help(arg)));
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+    out
+}
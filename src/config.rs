@@ -0,0 +1,69 @@
+//! Settings read from `config.json` in the config directory. Every setting
+//! is optional, e.g.
+//!
+//! ```json
+//! {
+//!   "color": { "clean": "green", "few": "yellow", "many": "red", "conflicted": "1;31", "threshold": 10 },
+//!   "icons": { "branch": "", "codes": { "M": "", "??": "" } },
+//!   "bar": { "clean": "#98c379", "few": "#e5c07b", "many": "#e06c75" },
+//!   "polybar": { "font": 2 },
+//!   "xmobar": { "icon": "/home/me/.xmobar/git.xbm" },
+//!   "backend": "sled",
+//!   "ttl": "90d",
+//!   "max_entries": 500,
+//!   "open_retry": { "attempts": 10, "backoff_ms": 100 },
+//!   "broker": { "idle": "5m" },
+//!   "backups": { "every": 100, "interval": "1d", "keep": 5 }
+//! }
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::backup::Rotation;
+use crate::bars::{self, Polybar, Xmobar};
+use crate::color::Colors;
+use crate::daemon::Broker;
+use crate::icons::Icons;
+use crate::store;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub color: Colors,
+    pub icons: Icons,
+    pub bar: bars::Colors,
+    pub polybar: Polybar,
+    pub xmobar: Xmobar,
+    /// The backend the database is kept in.
+    pub backend: Option<store::Kind>,
+    /// How long a status is kept without an update, like 90d, if it's
+    /// dropped at all.
+    pub ttl: Option<String>,
+    /// How many statuses to keep, dropping the least recently updated ones
+    /// beyond it on put.
+    pub max_entries: Option<usize>,
+    /// How opening the database waits while another invocation holds it.
+    pub open_retry: store::Retry,
+    /// Funnel writes through a daemon started on demand, if set.
+    pub broker: Option<Broker>,
+    /// Take backups as statuses are put, if set.
+    pub backups: Option<Rotation>,
+}
+
+impl Config {
+    /// Read the config in dir, or the defaults when there isn't one.
+    pub fn load(dir: &str) -> Result<Config, Box<dyn Error>> {
+        let path = Path::new(dir).join("config.json");
+        match fs::read_to_string(&path) {
+            Ok(config) => serde_json::from_str(&config)
+                .map_err(|e| format!("invalid config {}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! A long-running process that holds the database open and answers requests
+//! over a unix socket, so invocations don't contend for the sled lock.
+//!
+//! Requests and responses are frames of a JSON object after its length, as
+//! a 4-byte big-endian number, e.g. `\0\0\0\x06"List"`. Only the user who
+//! started the daemon can connect to its socket.
+//!
+//! A daemon started with an idle timeout is a broker: invocations start one
+//! on demand to write through, it exits once nothing has used it for the
+//! timeout, and it steps aside when asked so others can open the database.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{store, Database, Status};
+
+/// Settings for the broker started on demand.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Broker {
+    /// How long the broker waits for a request before exiting, like 5m.
+    pub idle: String,
+}
+
+impl Default for Broker {
+    fn default() -> Broker {
+        Broker {
+            idle: "5m".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Put(Status),
+    PutIfChanged(Status),
+    Get(String),
+    List,
+    Delete(String),
+    Stop,
+}
+
+/// The longest frame read, so a bad length can't exhaust memory.
+const MAX_FRAME: u32 = 64 << 20;
+
+/// Write value as a frame.
+fn write_frame<T: Serialize>(writer: &mut impl Write, value: &T) -> Result<(), Box<dyn Error>> {
+    let body = serde_json::to_vec(value)?;
+    let len = u32::try_from(body.len()).map_err(|_| "frame is too long")?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the body of a frame, or None when the connection was closed before
+/// one started.
+fn read_frame(reader: &mut impl Read) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME {
+        return Err(format!("frame of {} bytes is too long", len).into());
+    }
+    let mut body = vec![0; len as usize];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    Done,
+    Written(bool),
+    Status(Option<Box<Status>>),
+    Statuses(Vec<Status>),
+    Error(String),
+}
+
+/// What the connections of a broker share, to know when it's idle and to
+/// exit without dropping any of them.
+struct State {
+    last: Instant,
+    connections: usize,
+    /// Set once the broker has decided to exit, after which it only serves
+    /// the connections already made.
+    stopping: bool,
+    /// Set once the socket is gone, so nothing new can connect.
+    closed: bool,
+}
+
+type Activity = Arc<Mutex<State>>;
+
+/// Serve requests on the socket until the process is killed or, when idle is
+/// set, until no connection has been open for idle seconds.
+pub fn serve(db: Database, socket: &Path, idle: Option<u64>) -> Result<(), Box<dyn Error>> {
+    // A socket left behind by a daemon that didn't exit cleanly.
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    let activity = Arc::new(Mutex::new(State {
+        last: Instant::now(),
+        connections: 0,
+        stopping: false,
+        closed: false,
+    }));
+    if let Some(idle) = idle {
+        let activity = activity.clone();
+        let socket = socket.to_path_buf();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(100));
+            let mut state = activity.lock().unwrap();
+            if state.connections == 0 && state.last.elapsed() >= Duration::from_secs(idle) {
+                begin_stop(&mut state, &socket);
+                return;
+            }
+        });
+    }
+    let accept = |stream: UnixStream| -> Result<(), Box<dyn Error>> {
+        // Accepted streams would share the listener's non-blocking mode.
+        stream.set_nonblocking(false)?;
+        let db = db.clone();
+        let activity = activity.clone();
+        let socket = socket.to_path_buf();
+        activity.lock().unwrap().connections += 1;
+        thread::spawn(move || {
+            if let Err(e) = handle(db, stream, idle.is_some(), &activity, &socket) {
+                eprintln!("failed to handle connection: {}", e);
+            }
+            let mut state = activity.lock().unwrap();
+            state.last = Instant::now();
+            state.connections -= 1;
+        });
+        Ok(())
+    };
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => accept(stream)?,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            }
+        }
+        if !activity.lock().unwrap().stopping {
+            continue;
+        }
+        // Serve whoever connected before the socket was removed, then wait
+        // for every connection to finish before exiting.
+        while !activity.lock().unwrap().closed {
+            thread::sleep(Duration::from_millis(10));
+        }
+        listener.set_nonblocking(true)?;
+        while let Ok((stream, _)) = listener.accept() {
+            accept(stream)?;
+        }
+        drop(listener);
+        while activity.lock().unwrap().connections > 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        process::exit(0);
+    }
+    Ok(())
+}
+
+/// Start exiting: stop new connections by removing the socket, after
+/// waking the accept loop so it serves the ones already made and exits
+/// once they've finished, which releases the database lock.
+fn begin_stop(state: &mut State, socket: &Path) {
+    if state.stopping {
+        return;
+    }
+    state.stopping = true;
+    let wake = UnixStream::connect(socket);
+    let _ = fs::remove_file(socket);
+    state.closed = true;
+    drop(wake);
+}
+
+fn handle(
+    db: Database,
+    stream: UnixStream,
+    broker: bool,
+    activity: &Activity,
+    socket: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    while let Some(frame) = read_frame(&mut reader)? {
+        let request = serde_json::from_slice(&frame);
+        let stopping = broker && matches!(request, Ok(Request::Stop));
+        let response = match request {
+            Ok(Request::Stop) if broker => Response::Done,
+            Ok(Request::Stop) => {
+                Response::Error("only a daemon started with --idle steps aside".to_string())
+            }
+            Ok(request) => respond(db.clone(), request),
+            Err(e) => Response::Error(format!("invalid request: {}", e)),
+        };
+        write_frame(&mut writer, &response)?;
+        if stopping {
+            // This connection stays open until the process exits, which is
+            // how the client knows the lock has been released, so it isn't
+            // waited for.
+            let mut state = activity.lock().unwrap();
+            state.connections -= 1;
+            begin_stop(&mut state, socket);
+            drop(state);
+            loop {
+                thread::park();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn respond(db: Database, request: Request) -> Response {
+    let result = match request {
+        Request::Delete(path) => db.delete(&path).map(|_| Response::Done),
+        Request::Stop => Err("stop is handled by the connection".into()),
+        Request::Put(status) => db.update(status).map(|_| Response::Done),
+        Request::PutIfChanged(status) => db
+            .update_changed(&[status])
+            .map(|n| Response::Written(n > 0)),
+        Request::Get(path) => db.find(&path).map(|s| Response::Status(s.map(Box::new))),
+        Request::List => db.list().map(Response::Statuses),
+    };
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+/// Start a broker for the database of kind in dir that exits after idle
+/// seconds without a request, and connect to it once it's listening on
+/// socket. None means it didn't start listening in time.
+pub fn spawn(
+    dir: &str,
+    kind: store::Kind,
+    idle: u64,
+    socket: &Path,
+) -> Result<Option<Client>, Box<dyn Error>> {
+    let backend = kind
+        .to_possible_value()
+        .ok_or("the backend has no name")?
+        .get_name();
+    Command::new(std::env::current_exe()?)
+        .args(["--db", dir, "--backend", backend, "--socket"])
+        .arg(socket)
+        .args(["daemon", "--idle"])
+        .arg(format!("{}s", idle))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keep it running when the terminal that started it closes.
+        .process_group(0)
+        .spawn()?;
+    for _ in 0..100 {
+        if let Some(client) = Client::connect(socket) {
+            return Ok(Some(client));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    Ok(None)
+}
+
+/// A connection to a running daemon.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connect to the daemon listening on socket, if there is one.
+    pub fn connect(socket: &Path) -> Option<Client> {
+        let writer = UnixStream::connect(socket).ok()?;
+        let reader = BufReader::new(writer.try_clone().ok()?);
+        Some(Client { reader, writer })
+    }
+
+    fn send(&mut self, request: &Request) -> Result<Response, Box<dyn Error>> {
+        write_frame(&mut self.writer, request)?;
+        let frame = read_frame(&mut self.reader)?.ok_or("daemon closed the connection")?;
+        match serde_json::from_slice(&frame)? {
+            Response::Error(e) => Err(e.into()),
+            response => Ok(response),
+        }
+    }
+
+    pub fn update(&mut self, status: Status) -> Result<(), Box<dyn Error>> {
+        match self.send(&Request::Put(status))? {
+            Response::Done => Ok(()),
+            r => Err(format!("unexpected response from daemon: {:?}", r).into()),
+        }
+    }
+
+    /// Store the status unless it's unchanged, returning whether it was.
+    pub fn update_changed(&mut self, status: Status) -> Result<bool, Box<dyn Error>> {
+        match self.send(&Request::PutIfChanged(status))? {
+            Response::Written(written) => Ok(written),
+            r => Err(format!("unexpected response from daemon: {:?}", r).into()),
+        }
+    }
+
+    pub fn find(&mut self, path: &str) -> Result<Option<Status>, Box<dyn Error>> {
+        match self.send(&Request::Get(path.to_string()))? {
+            Response::Status(status) => Ok(status.map(|s| *s)),
+            r => Err(format!("unexpected response from daemon: {:?}", r).into()),
+        }
+    }
+
+    pub fn delete(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        match self.send(&Request::Delete(path.to_string()))? {
+            Response::Done => Ok(()),
+            r => Err(format!("unexpected response from daemon: {:?}", r).into()),
+        }
+    }
+
+    /// Ask a broker to exit so the database can be opened, returning
+    /// whether it has. Other daemons keep running.
+    pub fn stop(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.send(&Request::Stop) {
+            Ok(Response::Done) => {
+                // The broker holds the connection open until it exits and
+                // releases the lock.
+                let _ = read_frame(&mut self.reader);
+                Ok(true)
+            }
+            Ok(r) => Err(format!("unexpected response from daemon: {:?}", r).into()),
+            Err(_) => Ok(false),
+        }
+    }
+
+    pub fn list(&mut self) -> Result<Vec<Status>, Box<dyn Error>> {
+        match self.send(&Request::List)? {
+            Response::Statuses(statuses) => Ok(statuses),
+            r => Err(format!("unexpected response from daemon: {:?}", r).into()),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Comma and tab separated output for spreadsheets and awk.
+
+use clap::ArgEnum;
+
+use crate::{clock, template, Status};
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    fn separator(self) -> &'static str {
+        match self {
+            Format::Csv => ",",
+            Format::Tsv => "\t",
+        }
+    }
+
+    fn escape(self, field: &str) -> String {
+        match self {
+            Format::Csv if field.contains([',', '"', '\n', '\r']) => {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            }
+            Format::Csv => field.to_string(),
+            Format::Tsv => field.replace(['\t', '\n', '\r'], " "),
+        }
+    }
+
+    fn row(self, fields: &[String]) -> String {
+        fields
+            .iter()
+            .map(|f| self.escape(f))
+            .collect::<Vec<_>>()
+            .join(self.separator())
+    }
+}
+
+/// A header row followed by a row for each status, with the same columns
+/// whatever codes the statuses have.
+pub fn to_string(statuses: &[Status], format: Format) -> String {
+    let mut header = vec!["path".to_string(), "branch".to_string()];
+    header.extend(template::CATEGORIES.iter().map(|c| c.to_string()));
+    header.extend(["total".to_string(), "updated_at".to_string()]);
+
+    let mut out = format.row(&header) + "\n";
+    for status in statuses {
+        let mut row = vec![status.path.clone(), status.branch.clone()];
+        for category in template::CATEGORIES {
+            row.push(
+                template::category(status, category)
+                    .unwrap_or(0)
+                    .to_string(),
+            );
+        }
+        row.push(status.changes().to_string());
+        row.push(if status.updated_at > 0 {
+            clock::format(status.updated_at)
+        } else {
+            String::new()
+        });
+        out.push_str(&format.row(&row));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses() -> Vec<Status> {
+        let mut dirty = Status {
+            path: "/a".to_string(),
+            branch: "fix, \"quoted\"\tname".to_string(),
+            updated_at: 1645376645,
+            ..Default::default()
+        };
+        dirty.set_counts([("M".to_string(), 2), ("??".to_string(), 1)].into());
+        let clean = Status {
+            path: "/b".to_string(),
+            branch: "main".to_string(),
+            ..Default::default()
+        };
+        vec![dirty, clean]
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            to_string(&statuses(), Format::Csv),
+            "path,branch,modified,added,deleted,renamed,copied,conflicted,untracked,ignored,total,updated_at\n\
+             /a,\"fix, \"\"quoted\"\"\tname\",2,0,0,0,0,0,1,0,3,2022-02-20T17:04:05Z\n\
+             /b,main,0,0,0,0,0,0,0,0,0,\n"
+        );
+    }
+
+    #[test]
+    fn tsv() {
+        assert_eq!(
+            to_string(&statuses(), Format::Tsv),
+            "path\tbranch\tmodified\tadded\tdeleted\trenamed\tcopied\tconflicted\tuntracked\tignored\ttotal\tupdated_at\n\
+             /a\tfix, \"quoted\" name\t2\t0\t0\t0\t0\t0\t1\t0\t3\t2022-02-20T17:04:05Z\n\
+             /b\tmain\t0\t0\t0\t0\t0\t0\t0\t0\t0\t\n"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Consistency checks for the database. Values that can't be read are set
+//! aside in a quarantine keyspace rather than deleted, keyed by the
+//! keyspace they came from, a NUL and their key.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::history::Entry;
+use crate::store::{Space, StatusStore, Transaction};
+use crate::{Database, Status};
+
+#[derive(Debug)]
+pub enum Problem {
+    /// A key that isn't an encoded path.
+    BadKey { key: Vec<u8> },
+    /// A value that doesn't decode into a status.
+    Undecodable { key: Vec<u8>, error: String },
+    /// A status stored under a different path than its own.
+    Mismatched { key: String, status: Box<Status> },
+    /// A history entry that doesn't decode.
+    BadHistory { key: Vec<u8>, error: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::BadKey { key } => write!(f, "key {:?} is not a path", key),
+            Problem::Undecodable { key, error } => {
+                write!(
+                    f,
+                    "value for {} doesn't decode: {}",
+                    display_key(key),
+                    error
+                )
+            }
+            Problem::Mismatched { key, status } => {
+                write!(f, "status for {} is stored under {}", status.path, key)
+            }
+            Problem::BadHistory { key, error } => {
+                write!(f, "history entry {:?} doesn't decode: {}", key, error)
+            }
+        }
+    }
+}
+
+fn display_key(key: &[u8]) -> String {
+    String::from_utf8(key.to_vec()).unwrap_or_else(|_| format!("{:?}", key))
+}
+
+impl Database {
+    /// Find every problem in the database.
+    pub fn check(&self) -> Result<(usize, Vec<Problem>), Box<dyn Error>> {
+        let mut checked = 0;
+        let mut problems = Vec::new();
+        for (key, value) in self.statuses.iter()? {
+            checked += 1;
+            let path = match String::from_utf8(key.clone()) {
+                Ok(path) => path,
+                Err(_) => {
+                    problems.push(Problem::BadKey { key });
+                    continue;
+                }
+            };
+            match Status::decode(&value) {
+                Ok(status) if status.path != path => problems.push(Problem::Mismatched {
+                    key: path,
+                    status: Box::new(status),
+                }),
+                Ok(_) => {}
+                Err(e) => problems.push(Problem::Undecodable {
+                    key,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        for (key, value) in self.history.iter()? {
+            if let Err(e) = Entry::decode(&value) {
+                problems.push(Problem::BadHistory {
+                    key,
+                    error: e.to_string(),
+                });
+            }
+        }
+        Ok((checked, problems))
+    }
+
+    /// Move the value of key in store, the keyspace space, to the
+    /// quarantine, both in one transaction.
+    fn set_aside(
+        &self,
+        store: &dyn StatusStore,
+        space: Space,
+        key: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut transaction = Transaction::default();
+        if let Some(value) = store.get(key)? {
+            quarantine(&mut transaction, space, key, value);
+        }
+        transaction.batch(space).remove(key);
+        self.commit(transaction)
+    }
+
+    /// Fix the problems: statuses stored under the wrong key are moved to
+    /// their own path (unless a status is already there) and anything that
+    /// can't be read is quarantined.
+    pub fn repair(&self, problems: &[Problem]) -> Result<(), Box<dyn Error>> {
+        for problem in problems {
+            match problem {
+                Problem::BadKey { key } | Problem::Undecodable { key, .. } => {
+                    self.set_aside(self.statuses.as_ref(), Space::Statuses, key)?;
+                }
+                Problem::Mismatched { key, status } => {
+                    let mut transaction = Transaction::default();
+                    let batch = transaction.batch(Space::Statuses);
+                    batch.remove(key.as_bytes());
+                    if !self.exists(&status.path)? {
+                        batch.insert(status.path.as_bytes(), status.encode()?);
+                    }
+                    self.commit(transaction)?;
+                }
+                Problem::BadHistory { key, .. } => {
+                    self.set_aside(self.history.as_ref(), Space::History, key)?;
+                }
+            }
+        }
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    /// Clear the statuses and history and write back everything in them
+    /// that can be read, in the current layout, quarantining the rest.
+    /// Returns how many statuses and entries were written.
+    pub fn rebuild(&self) -> Result<(usize, usize), Box<dyn Error>> {
+        let mut counts = Vec::new();
+        let mut transaction = Transaction::default();
+        for (store, space) in [
+            (&self.statuses, Space::Statuses),
+            (&self.history, Space::History),
+        ] {
+            let mut rewritten = Vec::new();
+            // Everything in the keyspace is removed below, so what can't be
+            // read only has to be added to the quarantine.
+            for (key, value) in store.iter()? {
+                let encoded = if space == Space::Statuses {
+                    Status::decode(&value).and_then(|s| s.encode())
+                } else {
+                    Entry::decode(&value).and_then(|e| e.encode())
+                };
+                match encoded {
+                    Ok(value) => rewritten.push((key, value)),
+                    Err(_) => quarantine(&mut transaction, space, &key, value),
+                }
+            }
+            counts.push(rewritten.len());
+            let batch = transaction.batch(space);
+            for (key, _) in store.iter()? {
+                batch.remove(key);
+            }
+            for (key, value) in rewritten {
+                batch.insert(key, value);
+            }
+        }
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok((counts[0], counts[1]))
+    }
+}
+
+/// Add the value of key in space to the quarantine in transaction.
+fn quarantine(transaction: &mut Transaction, space: Space, key: &[u8], value: Vec<u8>) {
+    let mut quarantined = space.name().as_bytes().to_vec();
+    quarantined.push(0);
+    quarantined.extend_from_slice(key);
+    transaction
+        .batch(Space::Quarantine)
+        .insert(quarantined, value);
+}
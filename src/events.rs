@@ -0,0 +1,48 @@
+//! Changes to statuses made by this process, sent to whoever subscribed,
+//! like the WebSocket clients of `serve --http`.
+
+use std::sync::{mpsc, Mutex};
+
+use serde::Serialize;
+
+use crate::Status;
+
+/// A change, written as JSON like `{"event":"changed","status":{...}}` or
+/// `{"event":"removed","path":"/repo"}`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    Changed { status: Box<Status> },
+    Removed { path: String },
+}
+
+/// The subscribers to changes.
+#[derive(Default)]
+pub struct Events(Mutex<Vec<mpsc::Sender<Event>>>);
+
+impl Events {
+    /// Receive every change from now on.
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut senders) = self.0.lock() {
+            senders.push(sender);
+        }
+        receiver
+    }
+
+    /// Whether nobody has subscribed, so there's no need to work out what
+    /// changed.
+    pub fn is_empty(&self) -> bool {
+        self.0
+            .lock()
+            .map(|senders| senders.is_empty())
+            .unwrap_or(true)
+    }
+
+    /// Send event to every subscriber, forgetting those that have gone.
+    pub fn send(&self, event: Event) {
+        if let Ok(mut senders) = self.0.lock() {
+            senders.retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+}
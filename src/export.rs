@@ -0,0 +1,85 @@
+//! The file format used by export.
+//!
+//! An export is a document with a `version` number and a `statuses` list
+//! sorted by path. Each status has its `path`, `branch` and a `git_status`
+//! table mapping porcelain codes to counts, along with every other field it
+//! was stored with. Those follow the stored statuses, so they can be added
+//! to between releases without a new version; the version only changes
+//! with the shape of the document around them. In JSON:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "statuses": [
+//!     { "path": "/home/me/src/foo", "branch": "main", "git_status": { "??": 1, "M": 2 } }
+//!   ]
+//! }
+//! ```
+//!
+//! The TOML form has the same shape, with each status as a `[[statuses]]`
+//! table.
+
+use std::error::Error;
+
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{toml, Status};
+
+/// The version written to new exports.
+pub const VERSION: u32 = 1;
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Guess the format from a file's extension, defaulting to JSON.
+    pub fn from_path(path: &str) -> Format {
+        if path.ends_with(".toml") {
+            Format::Toml
+        } else {
+            Format::Json
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Export {
+    pub version: u32,
+    pub statuses: Vec<Status>,
+}
+
+impl Export {
+    pub fn new(mut statuses: Vec<Status>) -> Export {
+        statuses.sort_by(|x, y| x.path.cmp(&y.path));
+        Export {
+            version: VERSION,
+            statuses,
+        }
+    }
+
+    pub fn to_string(&self, format: Format) -> Result<String, Box<dyn Error>> {
+        match format {
+            Format::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+            Format::Toml => Ok(toml::to_string(&serde_json::to_value(self)?)?),
+        }
+    }
+
+    pub fn from_str(input: &str, format: Format) -> Result<Export, Box<dyn Error>> {
+        let export: Export = match format {
+            Format::Json => serde_json::from_str(input)?,
+            Format::Toml => serde_json::from_value(toml::from_str(input)?)?,
+        };
+        if export.version > VERSION {
+            return Err(format!(
+                "export version {} is newer than the supported version {}",
+                export.version, VERSION
+            )
+            .into());
+        }
+        Ok(export)
+    }
+}
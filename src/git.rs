@@ -0,0 +1,617 @@
+//! Helpers for collecting statuses by running git.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use crate::{Categories, Commit, HeadState, Remote, Status, Submodules};
+
+/// Run git in the given directory and return its trimmed stdout.
+fn run(dir: &Path, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed in {}: {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+}
+
+/// Count the occurrences of each XY code in `git status --porcelain` output.
+pub fn count_porcelain(porcelain: &str) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for code in porcelain.lines().filter_map(|l| l.get(..2)) {
+        *counts.entry(code.trim().to_string()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Sort the changes in `git status --porcelain` output into categories.
+pub fn categorize_porcelain(porcelain: &str) -> Categories {
+    let mut categories = Categories::default();
+    for code in porcelain.lines().filter_map(|l| l.get(..2)) {
+        categories.add(code, 1);
+    }
+    categories
+}
+
+/// Read the branch, upstream, ahead and behind counts and the counts of each
+/// code from `git status --porcelain=v2 --branch` output. Codes are counted
+/// like porcelain v1, e.g. `.M` as `M` and untracked files as `??`. A
+/// detached HEAD gets the short commit as its branch, e.g. `@abc1234`.
+pub fn parse_porcelain_v2(porcelain: &str) -> Status {
+    let mut status = Status::default();
+    let mut oid = "";
+    for line in porcelain.lines() {
+        if let Some(header) = line.strip_prefix("# ") {
+            match header.split_once(' ') {
+                Some(("branch.oid", "(initial)")) => status.head_state = HeadState::Unborn,
+                Some(("branch.oid", commit)) => oid = commit,
+                Some(("branch.head", "(detached)")) => status.head_state = HeadState::Detached,
+                Some(("branch.head", head)) => status.branch = head.to_string(),
+                Some(("branch.upstream", upstream)) => status.upstream = Some(upstream.to_string()),
+                Some(("branch.ab", ab)) => {
+                    for count in ab.split_whitespace() {
+                        let (sign, n) = count.split_at(1.min(count.len()));
+                        let n = n.parse().unwrap_or(0);
+                        match sign {
+                            "+" => status.ahead = n,
+                            "-" => status.behind = n,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let code = match line.split_once(' ') {
+            Some(("1" | "2" | "u", rest)) => rest.get(..2).unwrap_or_default().replace('.', " "),
+            Some(("?", _)) => "??".to_string(),
+            Some(("!", _)) => "!!".to_string(),
+            _ => continue,
+        };
+        status.categories.add(&code, 1);
+        *status
+            .git_status
+            .entry(code.trim().to_string())
+            .or_insert(0) += 1;
+    }
+    if status.head_state == HeadState::Detached {
+        status.branch = format!("@{}", oid.get(..7).unwrap_or(oid));
+    }
+    status
+}
+
+/// The operation in progress in the git directory, named like git's prompt
+/// names them, with the step for rebases and am, e.g. `REBASE 2/5`.
+fn operation(git_dir: &Path) -> Option<String> {
+    let read = |file: &str| {
+        fs::read_to_string(git_dir.join(file))
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+    let step = |dir: &str, step: &str, total: &str| match (
+        read(&format!("{}/{}", dir, step)),
+        read(&format!("{}/{}", dir, total)),
+    ) {
+        (Some(step), Some(total)) => format!(" {}/{}", step, total),
+        _ => String::new(),
+    };
+    if git_dir.join("rebase-merge").is_dir() {
+        return Some(format!("REBASE{}", step("rebase-merge", "msgnum", "end")));
+    }
+    if git_dir.join("rebase-apply").is_dir() {
+        let name = if git_dir.join("rebase-apply/rebasing").exists() {
+            "REBASE"
+        } else if git_dir.join("rebase-apply/applying").exists() {
+            "AM"
+        } else {
+            "AM/REBASE"
+        };
+        return Some(format!("{}{}", name, step("rebase-apply", "next", "last")));
+    }
+    [
+        ("MERGE_HEAD", "MERGING"),
+        ("CHERRY_PICK_HEAD", "CHERRY-PICKING"),
+        ("REVERT_HEAD", "REVERTING"),
+        ("BISECT_LOG", "BISECTING"),
+    ]
+    .iter()
+    .find(|(file, _)| git_dir.join(file).exists())
+    .map(|(_, name)| name.to_string())
+}
+
+/// The main worktree of the repository with the common git directory, which
+/// is the directory itself for bare repositories.
+fn main_worktree(common_dir: &Path) -> Option<String> {
+    let common_dir = fs::canonicalize(common_dir).ok()?;
+    let main = match common_dir.file_name() {
+        Some(name) if name == ".git" => common_dir.parent()?,
+        _ => &common_dir,
+    };
+    Some(main.to_string_lossy().to_string())
+}
+
+/// The remote of upstream if there is one, or else origin or the first
+/// remote.
+fn remote(path: &Path, upstream: Option<&str>) -> Option<Remote> {
+    let urls = run(path, &["config", "--get-regexp", r"^remote\..*\.url$"]).ok()?;
+    let remotes = urls
+        .lines()
+        .filter_map(|line| {
+            let (key, url) = line.split_once(' ')?;
+            let name = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some(Remote {
+                name: name.to_string(),
+                url: url.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let tracked = upstream.and_then(|upstream| {
+        remotes
+            .iter()
+            .filter(|r| upstream.starts_with(&format!("{}/", r.name)))
+            .max_by_key(|r| r.name.len())
+    });
+    tracked
+        .or_else(|| remotes.iter().find(|r| r.name == "origin"))
+        .or_else(|| remotes.first())
+        .cloned()
+}
+
+/// A URL without its scheme or user, with SSH's `host:path` turned into
+/// `host/path`, so `git@github.com:me/repo` becomes `github.com/me/repo`.
+pub fn normalize_url(url: &str) -> String {
+    let (scp, rest) = match url.split_once("://") {
+        Some((_, rest)) => (false, rest),
+        None => (true, url),
+    };
+    let rest = match rest.split_once('@') {
+        Some((user, host)) if !user.contains('/') => host,
+        _ => rest,
+    };
+    if scp {
+        rest.replacen(':', "/", 1)
+    } else {
+        rest.to_string()
+    }
+}
+
+/// Whether the worktree is a sparse checkout.
+fn sparse(path: &Path) -> bool {
+    run(path, &["config", "--type=bool", "core.sparseCheckout"]).is_ok_and(|s| s == "true")
+}
+
+/// The nearest tag at or after HEAD, e.g. `v1.2.3~2` for two commits
+/// before v1.2.3, if there is one.
+fn nearest_tag(path: &Path) -> Option<String> {
+    let name = run(
+        path,
+        &[
+            "name-rev",
+            "--name-only",
+            "--tags",
+            "--no-undefined",
+            "HEAD",
+        ],
+    )
+    .ok()?;
+    Some(name.trim_end_matches("^0").to_string()).filter(|n| !n.is_empty())
+}
+
+/// The last commit on HEAD, or none in a repository without commits.
+fn last_commit(path: &Path) -> Option<Commit> {
+    let log = run(path, &["log", "-1", "--format=%h%x00%at%x00%s"]).ok()?;
+    let mut fields = log.splitn(3, '\0');
+    Some(Commit {
+        hash: fields.next()?.to_string(),
+        date: fields.next()?.parse().ok()?,
+        subject: fields.next()?.to_string(),
+    })
+}
+
+/// How many stashes the repository has, read from the reflog of refs/stash
+/// in the common git directory so it takes no git process. Each stash is a
+/// line in the reflog.
+fn stashes(common_dir: &Path) -> Result<u64, Box<dyn Error>> {
+    match fs::read_to_string(common_dir.join("logs/refs/stash")) {
+        Ok(reflog) => Ok(reflog.lines().filter(|l| !l.is_empty()).count() as u64),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What to collect besides the branch and status.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Count the modified and uninitialized submodules.
+    pub submodules: bool,
+    /// Count ignored files, under the `!!` code.
+    pub ignored: bool,
+}
+
+/// Count the submodules in `git status --porcelain=v2` output with a new
+/// commit, changes or untracked files, and those `git submodule status`
+/// reports as uninitialized.
+fn submodules(path: &Path, porcelain: &str) -> Result<Submodules, Box<dyn Error>> {
+    let modified = porcelain
+        .lines()
+        .filter_map(|line| line.split(' ').nth(2))
+        .filter(|sub| sub.starts_with('S') && *sub != "S...")
+        .count() as u64;
+    let uninitialized = run(path, &["submodule", "status"])?
+        .lines()
+        .filter(|line| line.starts_with('-'))
+        .count() as u64;
+    Ok(Submodules {
+        modified,
+        uninitialized,
+    })
+}
+
+/// Collect the branch and porcelain status of the repository at path.
+pub fn read(path: &Path, options: &Options) -> Result<Status, Box<dyn Error>> {
+    let dirs = run(
+        path,
+        &[
+            "rev-parse",
+            "--is-bare-repository",
+            "--is-shallow-repository",
+            "--git-dir",
+            "--git-common-dir",
+        ],
+    )?;
+    let mut dirs = dirs.lines();
+    let bare = dirs.next() == Some("true");
+    let shallow = dirs.next() == Some("true");
+    let mut dirs = dirs.map(|dir| path.join(dir));
+    let git_dir = dirs.next().unwrap_or_else(|| path.join(".git"));
+    let common_dir = dirs.next().unwrap_or_else(|| git_dir.clone());
+    let repo_id = main_worktree(&common_dir);
+    if bare {
+        return Ok(Status {
+            path: path.to_string_lossy().to_string(),
+            branch: run(path, &["symbolic-ref", "--short", "HEAD"]).unwrap_or_default(),
+            commit: last_commit(path),
+            remote: remote(path, None),
+            repo_id,
+            bare,
+            shallow,
+            ..Default::default()
+        });
+    }
+    let mut args = vec!["status", "--porcelain=v2", "--branch"];
+    if options.ignored {
+        args.push("--ignored");
+    }
+    let porcelain = run(path, &args)?;
+    let mut status = parse_porcelain_v2(&porcelain);
+    if status.head_state == HeadState::Detached {
+        if let Some(tag) = nearest_tag(path) {
+            status.branch = tag;
+        }
+    }
+    if options.submodules {
+        status.submodules = Some(submodules(path, &porcelain)?);
+    }
+    Ok(Status {
+        path: path.to_string_lossy().to_string(),
+        stashes: stashes(&common_dir)?,
+        commit: last_commit(path),
+        operation: operation(&git_dir),
+        remote: remote(path, status.upstream.as_deref()),
+        repo_id,
+        shallow,
+        sparse: sparse(path),
+        ..status
+    })
+}
+
+/// What can be seen of a repository without running git: the latest time
+/// the index, `HEAD`, the refs or any directory of the worktree was
+/// modified, and how many directories there are. Adding, removing or
+/// renaming a file changes it, as does anything git does, but a file
+/// rewritten in place is only seen once something else changes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Fingerprint {
+    directories: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn add(&mut self, path: &Path) {
+        if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+            self.modified = self.modified.max(Some(modified));
+        }
+    }
+}
+
+/// The directories under path that git ignores, which fingerprint skips.
+/// Errors leave it empty, which only means more is looked at.
+pub fn ignored_directories(path: &Path) -> Vec<PathBuf> {
+    let args = [
+        "ls-files",
+        "-z",
+        "--others",
+        "--ignored",
+        "--exclude-standard",
+        "--directory",
+    ];
+    let output = run(path, &args).unwrap_or_default();
+    output
+        .split('\0')
+        .filter_map(|entry| entry.strip_suffix('/'))
+        .map(|entry| path.join(entry))
+        .collect()
+}
+
+/// The fingerprint of the worktree at path, leaving out its git directory
+/// and the ignored directories. Only directories are walked and only their
+/// own modification times taken, so it costs a stat per directory rather
+/// than per file. Worktrees whose git directory is elsewhere, and bare
+/// repositories, have none, so git has to be asked.
+pub fn fingerprint(path: &Path, ignored: &[PathBuf]) -> Option<Fingerprint> {
+    let git_dir = path.join(".git");
+    if !git_dir.is_dir() {
+        return None;
+    }
+    let mut fingerprint = Fingerprint::default();
+    // The git directory itself changes when a merge, rebase or the like
+    // starts or stops, and refs/ and packed-refs when a ref moves.
+    for file in ["", "index", "HEAD", "packed-refs"] {
+        fingerprint.add(&git_dir.join(file));
+    }
+    let mut pending = vec![git_dir.join("refs"), path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        fingerprint.directories += 1;
+        fingerprint.add(&dir);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            // Like git, symbolic links are seen rather than followed.
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let path = entry.path();
+            if is_dir && path != git_dir && !ignored.contains(&path) {
+                pending.push(path);
+            }
+        }
+    }
+    Some(fingerprint)
+}
+
+/// Whether dir looks like a bare repository.
+fn is_bare(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// Find the git repositories under root, bare ones included, descending at
+/// most max_depth directories. Repositories are not searched for nested
+/// repositories.
+pub fn find_repositories(
+    root: &Path,
+    max_depth: Option<usize>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut repositories = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        if dir.join(".git").exists() || is_bare(&dir) {
+            repositories.push(dir);
+            continue;
+        }
+        if max_depth.is_some_and(|max| depth >= max) {
+            continue;
+        }
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                pending.push((entry.path(), depth + 1));
+            }
+        }
+    }
+    repositories.sort();
+    Ok(repositories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts from code and count pairs.
+    fn counts(pairs: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        pairs.iter().map(|(c, n)| (c.to_string(), *n)).collect()
+    }
+
+    #[test]
+    fn porcelain_v1() {
+        let table: &[(&str, &[(&str, u64)])] = &[
+            ("", &[]),
+            (" M a\n M b\n", &[("M", 2)]),
+            ("M  a\nMM b\n?? c\n", &[("M", 1), ("MM", 1), ("??", 1)]),
+            ("R  old -> new\n", &[("R", 1)]),
+            ("UU a\nAA b\nDD c\n", &[("UU", 1), ("AA", 1), ("DD", 1)]),
+            ("!! target/\n", &[("!!", 1)]),
+        ];
+        for (porcelain, expected) in table {
+            assert_eq!(
+                count_porcelain(porcelain),
+                counts(expected),
+                "{:?}",
+                porcelain
+            );
+        }
+    }
+
+    #[test]
+    fn porcelain_v1_categories() {
+        let categories = categorize_porcelain("M  a\n M b\nMM c\nR  d -> e\nUD f\n?? g\n");
+        assert_eq!(
+            categories,
+            Categories {
+                staged: 3,
+                unstaged: 2,
+                untracked: 1,
+                conflicted: 1,
+                modified: 3,
+                renamed: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    /// The status porcelain v2 headers describe.
+    fn head(branch: &str, head_state: HeadState, upstream: Option<&str>, ab: (u64, u64)) -> Status {
+        Status {
+            branch: branch.to_string(),
+            head_state,
+            upstream: upstream.map(str::to_string),
+            ahead: ab.0,
+            behind: ab.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn porcelain_v2_headers() {
+        let table = [
+            (
+                "# branch.oid 1234567890abcdef\n# branch.head main\n\
+                 # branch.upstream origin/main\n# branch.ab +2 -1\n",
+                head("main", HeadState::Branch, Some("origin/main"), (2, 1)),
+            ),
+            (
+                "# branch.oid 1234567890abcdef\n# branch.head topic\n",
+                head("topic", HeadState::Branch, None, (0, 0)),
+            ),
+            (
+                "# branch.oid abc1234def5678\n# branch.head (detached)\n",
+                head("@abc1234", HeadState::Detached, None, (0, 0)),
+            ),
+            (
+                "# branch.oid (initial)\n# branch.head main\n",
+                head("main", HeadState::Unborn, None, (0, 0)),
+            ),
+            // The upstream is gone, so there's no branch.ab.
+            (
+                "# branch.oid 1234567890abcdef\n# branch.head main\n# branch.upstream origin/gone\n",
+                head("main", HeadState::Branch, Some("origin/gone"), (0, 0)),
+            ),
+        ];
+        for (porcelain, expected) in table {
+            assert_eq!(parse_porcelain_v2(porcelain), expected, "{:?}", porcelain);
+        }
+    }
+
+    #[test]
+    fn porcelain_v2_entries() {
+        let porcelain = "\
+# branch.oid 1234567890abcdef
+# branch.head main
+1 .M N... 100644 100644 100644 aaaa bbbb modified.rs
+1 M. N... 100644 100644 100644 aaaa bbbb staged.rs
+1 A. N... 000000 100644 100644 0000 bbbb added.rs
+1 .D N... 100644 100644 000000 aaaa aaaa deleted.rs
+2 R. N... 100644 100644 100644 aaaa aaaa R100 new.rs\told.rs
+2 RM N... 100644 100644 100644 aaaa aaaa R90 edited.rs\tmoved.rs
+u UU N... 100644 100644 100644 100644 aaaa bbbb cccc both.rs
+u UD N... 100644 100644 000000 100644 aaaa bbbb cccc theirs.rs
+? untracked.rs
+? also untracked.rs
+! target/
+";
+        let status = parse_porcelain_v2(porcelain);
+        assert_eq!(
+            status.git_status,
+            counts(&[
+                ("M", 2),
+                ("A", 1),
+                ("D", 1),
+                ("R", 1),
+                ("RM", 1),
+                ("UU", 1),
+                ("UD", 1),
+                ("??", 2),
+                ("!!", 1),
+            ])
+        );
+        assert_eq!(
+            status.categories,
+            Categories {
+                staged: 4,
+                unstaged: 3,
+                untracked: 2,
+                ignored: 1,
+                conflicted: 2,
+                modified: 3,
+                added: 1,
+                deleted: 1,
+                renamed: 2,
+                copied: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_lines_are_skipped() {
+        let status = parse_porcelain_v2("# branch.future thing\nwhat is this\n\n");
+        assert_eq!(status, Status::default());
+    }
+
+    #[test]
+    fn urls() {
+        let table = [
+            ("git@github.com:me/repo.git", "github.com/me/repo.git"),
+            ("https://github.com/me/repo", "github.com/me/repo"),
+            ("ssh://git@host:22/org/repo", "host:22/org/repo"),
+            ("https://user@host/org/repo", "host/org/repo"),
+            ("/srv/git/repo", "/srv/git/repo"),
+        ];
+        for (url, normalized) in table {
+            assert_eq!(normalize_url(url), normalized, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn fingerprints() {
+        let dir = std::env::temp_dir().join(format!(
+            "git-status-tracker-fingerprint-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in [".git/refs/heads", "src", "target"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join(".git/index"), "").unwrap();
+        let ignored = [dir.join("target")];
+        let changes = |change: &dyn Fn()| {
+            let before = fingerprint(&dir, &ignored).unwrap();
+            // Outlast the coarse clock file times are taken from.
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            change();
+            before != fingerprint(&dir, &ignored).unwrap()
+        };
+
+        assert!(fingerprint(&dir.join("src"), &ignored).is_none());
+        assert!(changes(&|| fs::write(dir.join("src/a"), "a").unwrap()));
+        assert!(changes(&|| fs::create_dir(dir.join("src/b")).unwrap()));
+        assert!(changes(&|| fs::write(dir.join(".git/index"), "i").unwrap()));
+        assert!(changes(
+            &|| fs::write(dir.join(".git/refs/heads/main"), "").unwrap()
+        ));
+        assert!(!changes(&|| fs::write(dir.join("target/out"), "o").unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
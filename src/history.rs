@@ -0,0 +1,211 @@
+//! A bounded, timestamped history of each path's status, kept in a separate
+//! sled tree. Keys are the path key followed by the big-endian time in
+//! microseconds, so each path's entries are contiguous and in order.
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::migrations::{self, Stored};
+use crate::store::{Space, Transaction};
+use crate::{clock, Commit, Database, Status};
+
+/// The index of the entry that was current at timestamp, if there was one.
+pub fn position_at(entries: &[Entry], timestamp: u64) -> Option<usize> {
+    entries.iter().rposition(|e| e.timestamp <= timestamp)
+}
+
+/// Describe what changed between two statuses, one line per change.
+pub fn changes(from: &Status, to: &Status) -> Vec<String> {
+    let mut changes = Vec::new();
+    if from.branch != to.branch {
+        changes.push(format!("branch: {} -> {}", from.branch, to.branch));
+    }
+    if from.ahead != to.ahead {
+        changes.push(format!("ahead: {} -> {}", from.ahead, to.ahead));
+    }
+    if from.behind != to.behind {
+        changes.push(format!("behind: {} -> {}", from.behind, to.behind));
+    }
+    if from.upstream != to.upstream {
+        let name = |u: &Option<String>| u.clone().unwrap_or_else(|| "none".to_string());
+        changes.push(format!(
+            "upstream: {} -> {}",
+            name(&from.upstream),
+            name(&to.upstream)
+        ));
+    }
+    if from.commit != to.commit {
+        let hash = |c: &Option<Commit>| c.as_ref().map_or("none".to_string(), |c| c.hash.clone());
+        changes.push(format!(
+            "commit: {} -> {}",
+            hash(&from.commit),
+            hash(&to.commit)
+        ));
+    }
+    if from.stashes != to.stashes {
+        changes.push(format!("stashes: {} -> {}", from.stashes, to.stashes));
+    }
+    let mut codes = from
+        .git_status
+        .keys()
+        .chain(to.git_status.keys())
+        .collect::<Vec<_>>();
+    codes.sort();
+    codes.dedup();
+    for code in codes {
+        let old = from.git_status.get(code).copied().unwrap_or(0);
+        let new = to.git_status.get(code).copied().unwrap_or(0);
+        if old != new {
+            let delta = new as i64 - old as i64;
+            changes.push(format!("{}: {} -> {} ({:+})", code, old, new, delta));
+        }
+    }
+    changes
+}
+
+/// How many entries are kept for each path.
+pub const LIMIT: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: u64,
+    pub status: Status,
+}
+
+impl Entry {
+    pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        migrations::encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Entry, Box<dyn Error>> {
+        migrations::decode(bytes, Stored::Entry)
+    }
+}
+
+/// The prefix of the keys of path's entries: the path and a NUL, which no
+/// path has, so one path's entries never run into another's.
+pub fn prefix(path: &str) -> Vec<u8> {
+    let mut prefix = path.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+impl Database {
+    /// Add the statuses to their paths' histories in transaction. Statuses
+    /// that haven't changed since the last entry are skipped.
+    pub fn record(
+        &self,
+        transaction: &mut Transaction,
+        statuses: &[Status],
+    ) -> Result<(), Box<dyn Error>> {
+        let batch = transaction.batch(Space::History);
+        for status in statuses {
+            let prefix = prefix(&status.path);
+            let entries = self.history.scan_prefix(&prefix)?;
+            if let Some((_, last)) = entries.last() {
+                let last = Entry::decode(last)?;
+                if last.status.same_as(status) {
+                    continue;
+                }
+            }
+
+            // Make room for the new entry.
+            for (key, _) in entries
+                .iter()
+                .take((entries.len() + 1).saturating_sub(LIMIT))
+            {
+                batch.remove(key.as_slice());
+            }
+            let mut key = prefix;
+            key.extend_from_slice(&clock::now_micros().to_be_bytes());
+            let entry = Entry {
+                timestamp: clock::now(),
+                status: status.clone(),
+            };
+            batch.insert(key, entry.encode()?);
+        }
+        Ok(())
+    }
+
+    /// The history of path, oldest first.
+    pub fn history_of(&self, path: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (_, v) in self.history.scan_prefix(&prefix(path))? {
+            entries.push(Entry::decode(&v)?);
+        }
+        Ok(entries)
+    }
+
+    /// Every path's history with the time in microseconds each entry is
+    /// keyed by.
+    pub fn all_history(&self) -> Result<Vec<(u64, Entry)>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (key, value) in self.history.iter()? {
+            let time = key
+                .len()
+                .checked_sub(8)
+                .and_then(|i| key[i..].try_into().ok())
+                .map(u64::from_be_bytes)
+                .ok_or_else(|| format!("history key {:?} has no time", key))?;
+            entries.push((time, Entry::decode(&value)?));
+        }
+        Ok(entries)
+    }
+
+    /// Insert entries keyed by their time in microseconds, as returned by
+    /// all_history, in transaction.
+    pub fn insert_history(
+        &self,
+        transaction: &mut Transaction,
+        entries: &[(u64, Entry)],
+    ) -> Result<(), Box<dyn Error>> {
+        let batch = transaction.batch(Space::History);
+        for (time, entry) in entries {
+            let mut key = prefix(&entry.status.path);
+            key.extend_from_slice(&time.to_be_bytes());
+            batch.insert(key, entry.encode()?);
+        }
+        Ok(())
+    }
+
+    /// Remove the history of path in transaction.
+    pub fn forget(&self, transaction: &mut Transaction, path: &str) -> Result<(), Box<dyn Error>> {
+        let batch = transaction.batch(Space::History);
+        for (key, _) in self.history.scan_prefix(&prefix(path))? {
+            batch.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Move the history of from to to in transaction, updating the path of
+    /// each entry.
+    pub fn move_history(
+        &self,
+        transaction: &mut Transaction,
+        from: &str,
+        to: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let from_prefix = prefix(from);
+        let to_prefix = prefix(to);
+        let batch = transaction.batch(Space::History);
+        for (key, value) in self.history.scan_prefix(&from_prefix)? {
+            let mut entry = Entry::decode(&value)?;
+            entry.status.path = to.to_string();
+            let mut new_key = to_prefix.clone();
+            new_key.extend_from_slice(&key[from_prefix.len()..]);
+            batch.insert(new_key, entry.encode()?);
+            batch.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Remove every path's history in transaction.
+    pub fn clear_history(&self, transaction: &mut Transaction) -> Result<(), Box<dyn Error>> {
+        let batch = transaction.batch(Space::History);
+        for (key, _) in self.history.iter()? {
+            batch.remove(key);
+        }
+        Ok(())
+    }
+}
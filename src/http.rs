@@ -0,0 +1,349 @@
+//! A JSON API over HTTP, for dashboards, widgets and other machines:
+//!
+//! - `GET /status/{path}`: the status of path, or 404 when it isn't tracked.
+//! - `PUT /status/{path}`: put the status in the body, e.g.
+//!   `{"branch": "main", "git_status": {"M": 2}}`, returning it as stored.
+//! - `GET /statuses`: every status.
+//! - `GET /summary`: the counts across every status, as `stats` shows them.
+//! - `GET /events`: a WebSocket that's sent an event, like
+//!   `{"event":"changed","status":{...}}`, for every change made through
+//!   this server or its socket.
+//!
+//! The path in a URL is percent-decoded and gets a leading `/`, so
+//! `/status/home/me/repo` is the status of `/home/me/repo`. Errors are
+//! `{"error": "..."}`. There's no authentication, so only listen on
+//! addresses that only you can reach.
+
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::{normalize_path, stats, websocket, Database, Status};
+
+/// The largest request body read.
+const MAX_BODY: usize = 1 << 20;
+
+/// The largest request line and headers read, together.
+const MAX_HEAD: usize = 16 << 10;
+
+/// The most headers read.
+const MAX_HEADERS: usize = 100;
+
+/// How long a request may take to arrive before the connection is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often an idle WebSocket is pinged, to notice clients that went away
+/// without closing.
+const PING_EVERY: Duration = Duration::from_secs(30);
+
+/// How long a write to a WebSocket may stall before the client is taken to
+/// be gone.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the events loop checks whether the client has closed.
+const TICK: Duration = Duration::from_millis(200);
+
+/// Serve the API on addr until the process is killed.
+pub fn serve(db: Database, addr: &str) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let db = db.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(db, stream) {
+                eprintln!("failed to handle connection: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// The headers, with lowercase names.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP status code and the JSON to send with it.
+type Response = (u16, Value);
+
+fn handle(db: Database, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let response = match read_request(&mut BufReader::new(stream)) {
+        Ok(request) if request.path == "/events" => return events(&db, &request, writer),
+        Ok(request) => respond(&db, request),
+        Err(e) => error(400, e),
+    };
+    write_response(&mut writer, response)
+}
+
+/// Upgrade to a WebSocket and send it every change until it's closed.
+fn events(db: &Database, request: &Request, mut writer: TcpStream) -> Result<(), Box<dyn Error>> {
+    let key = match request.header("sec-websocket-key") {
+        Some(key) if request.method == "GET" => key,
+        _ => return write_response(&mut writer, error(400, "/events is a WebSocket")),
+    };
+    let events = db.events.subscribe();
+    writer.write_all(websocket::handshake(key).as_bytes())?;
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    // Frames may come seldom, or never; pings notice a client that's gone.
+    writer.set_read_timeout(None)?;
+
+    // The client's frames are only read to notice it closing, whether with
+    // a close frame or by dropping the connection.
+    let (closing, closed) = mpsc::channel();
+    let mut reader = writer.try_clone()?;
+    thread::spawn(move || {
+        let close = loop {
+            match websocket::read_frame(&mut reader) {
+                Ok(Some(websocket::CLOSE)) => break true,
+                Ok(Some(_)) => {}
+                Ok(None) | Err(_) => break false,
+            }
+        };
+        let _ = closing.send(close);
+    });
+
+    let mut written = Instant::now();
+    loop {
+        match closed.try_recv() {
+            Ok(close) => {
+                if close {
+                    let _ = websocket::close(&mut writer);
+                }
+                break;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => break,
+        }
+        let sent = match events.recv_timeout(TICK) {
+            Ok(event) => websocket::send(&mut writer, &serde_json::to_string(&event)?),
+            Err(RecvTimeoutError::Timeout) if written.elapsed() >= PING_EVERY => {
+                websocket::ping(&mut writer)
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        // A client that's gone without a word shows up as a failed write.
+        if sent.is_err() {
+            break;
+        }
+        written = Instant::now();
+    }
+    // Which also ends the reads.
+    let _ = writer.shutdown(Shutdown::Both);
+    Ok(())
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request, Box<dyn Error>> {
+    let mut head = reader.take(MAX_HEAD as u64);
+    // A line without its newline was cut short by the limit, unless the
+    // client stopped sending.
+    let mut read_line = |line: &mut String| -> Result<usize, Box<dyn Error>> {
+        let read = head.read_line(line)?;
+        if read > 0 && !line.ends_with('\n') && head.limit() == 0 {
+            return Err(format!("request head is longer than {} bytes", MAX_HEAD).into());
+        }
+        Ok(read)
+    };
+    let mut line = String::new();
+    read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(format!("invalid request line {:?}", line.trim_end()).into()),
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(format!("more than {} headers", MAX_HEADERS).into());
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+            if name == "content-length" {
+                length = value
+                    .parse()
+                    .map_err(|_| format!("invalid content length {}", value))?;
+            }
+            headers.push((name, value));
+        }
+    }
+    if length > MAX_BODY {
+        return Err(format!("body of {} bytes is too long", length).into());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn error(code: u16, e: impl std::fmt::Display) -> Response {
+    (code, json!({ "error": e.to_string() }))
+}
+
+fn respond(db: &Database, request: Request) -> Response {
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/statuses") => db.list().map(|statuses| (200, json!(statuses))),
+        ("GET", "/summary") => db.list().map(|statuses| {
+            let stats = stats::Stats::new(&statuses);
+            (200, json!(stats))
+        }),
+        (method, path) if path.starts_with("/status/") => {
+            let path = match decode(&path["/status/".len()..]) {
+                Ok(path) => normalize_path(&format!("/{}", path.trim_start_matches('/'))),
+                Err(e) => return error(400, e),
+            };
+            match method {
+                "GET" => db.find(&path).map(|status| match status {
+                    Some(status) => (200, json!(status)),
+                    None => error(404, format!("no status found for {}", path)),
+                }),
+                "PUT" => put(db, &path, &request.body),
+                _ => Ok(error(405, format!("{} isn't allowed on a status", method))),
+            }
+        }
+        _ => Ok(error(404, format!("nothing at {}", request.path))),
+    };
+    result.unwrap_or_else(|e| error(500, e))
+}
+
+/// Put the status in body for path, returning it as stored.
+fn put(db: &Database, path: &str, body: &[u8]) -> Result<Response, Box<dyn Error>> {
+    let mut value: Value = match serde_json::from_slice(body) {
+        Ok(value @ Value::Object(_)) => value,
+        Ok(_) => return Ok(error(400, "the body must be a JSON object")),
+        Err(e) => return Ok(error(400, format!("invalid body: {}", e))),
+    };
+    value["path"] = json!(path);
+    let guess = value.get("categories").is_none();
+    let mut status: Status = match serde_json::from_value(value) {
+        Ok(status) => status,
+        Err(e) => return Ok(error(400, format!("invalid status: {}", e))),
+    };
+    if guess {
+        let counts = std::mem::take(&mut status.git_status);
+        status.set_counts(counts);
+    }
+    db.update_all(&[status])?;
+    Ok((200, json!(db.find(path)?)))
+}
+
+/// Decode the %XX escapes in a URL path.
+fn decode(path: &str) -> Result<String, String> {
+    let mut decoded = Vec::new();
+    let mut bytes = path.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let hex = [bytes.next(), bytes.next()];
+        let byte = match hex {
+            [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        decoded.push(byte.ok_or_else(|| format!("bad escape in {}", path))?);
+    }
+    String::from_utf8(decoded).map_err(|_| format!("{} isn't UTF-8", path))
+}
+
+fn write_response(writer: &mut impl Write, (code, body): Response) -> Result<(), Box<dyn Error>> {
+    let reason = match code {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = serde_json::to_vec(&body)?;
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code,
+        reason,
+        body.len()
+    )?;
+    writer.write_all(&body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &str) -> Result<Request, Box<dyn Error>> {
+        read_request(&mut request.as_bytes())
+    }
+
+    #[test]
+    fn requests() {
+        let request =
+            read("PUT /status/a?x=1 HTTP/1.1\r\nContent-Length: 2\r\nX-A: b\r\n\r\n{}").unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("PUT", "/status/a")
+        );
+        assert_eq!(request.header("x-a"), Some("b"));
+        assert_eq!(request.body, b"{}");
+        assert!(read("nonsense\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let long = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_HEAD));
+        let e = read(&long).err().unwrap().to_string();
+        assert_eq!(e, "request head is longer than 16384 bytes");
+
+        let header = "X-A: b\r\n";
+        let many = format!("GET / HTTP/1.1\r\n{}\r\n", header.repeat(MAX_HEADERS + 1));
+        assert_eq!(
+            read(&many).err().unwrap().to_string(),
+            "more than 100 headers"
+        );
+        let most = format!("GET / HTTP/1.1\r\n{}\r\n", header.repeat(MAX_HEADERS));
+        assert_eq!(read(&most).unwrap().headers.len(), MAX_HEADERS);
+
+        let body = format!("PUT / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(
+            read(&body).err().unwrap().to_string(),
+            "body of 1048577 bytes is too long"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Glyphs for a compact, prompt-ready line like ` main  2  1`. The
+//! defaults need a Nerd Font; any of them can be changed in the config.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::Status;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Icons {
+    pub branch: String,
+    pub ahead: String,
+    pub behind: String,
+    pub stashes: String,
+    /// Glyphs for porcelain codes, added to the defaults. Codes without one
+    /// are shown as is.
+    #[serde(deserialize_with = "with_default_codes")]
+    pub codes: BTreeMap<String, String>,
+}
+
+fn default_codes() -> BTreeMap<String, String> {
+    [
+        ("M", "\u{f040}"),
+        ("A", "\u{f067}"),
+        ("D", "\u{f068}"),
+        ("R", "\u{f0ec}"),
+        ("C", "\u{f0c5}"),
+        ("U", "\u{f071}"),
+        ("??", "\u{f128}"),
+    ]
+    .iter()
+    .map(|(c, i)| (c.to_string(), i.to_string()))
+    .collect()
+}
+
+fn with_default_codes<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut codes = default_codes();
+    codes.extend(BTreeMap::deserialize(deserializer)?);
+    Ok(codes)
+}
+
+impl Default for Icons {
+    fn default() -> Icons {
+        Icons {
+            branch: "\u{e0a0}".to_string(),
+            ahead: "⇡".to_string(),
+            behind: "⇣".to_string(),
+            stashes: "\u{f01c}".to_string(),
+            codes: default_codes(),
+        }
+    }
+}
+
+impl Icons {
+    /// The status on one line with glyphs in place of names.
+    pub fn render(&self, status: &Status) -> String {
+        let mut parts = Vec::new();
+        if !status.branch.is_empty() {
+            parts.push(format!("{} {}", self.branch, status.branch));
+        }
+        for (code, count) in &status.git_status {
+            let icon = self.codes.get(code).unwrap_or(code);
+            parts.push(format!("{} {}", icon, count));
+        }
+        for (icon, count) in [
+            (&self.ahead, status.ahead),
+            (&self.behind, status.behind),
+            (&self.stashes, status.stashes),
+        ] {
+            if count > 0 {
+                parts.push(format!("{}{}", icon, count));
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        Status {
+            branch: "main".to_string(),
+            git_status: [("M".to_string(), 2), ("XY".to_string(), 1)].into(),
+            ahead: 1,
+            stashes: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_glyphs() {
+        assert_eq!(
+            Icons::default().render(&status()),
+            "\u{e0a0} main \u{f040} 2 XY 1 ⇡1 \u{f01c}3"
+        );
+        let clean = Status::default();
+        assert_eq!(Icons::default().render(&clean), "");
+    }
+
+    #[test]
+    fn configured_glyphs_add_to_the_defaults() {
+        let icons: Icons =
+            serde_json::from_str(r#"{"branch": "b", "stashes": "s", "codes": {"XY": "x"}}"#)
+                .unwrap();
+        assert_eq!(icons.render(&status()), "b main \u{f040} 2 x 1 ⇡1 s3");
+        assert!(serde_json::from_str::<Icons>(r#"{"nope": ""}"#).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+//! Shell hooks that put the status of the current repository before each
+//! prompt, e.g. `eval "$(git-status-tracker init zsh)"`.
+
+use crate::completions::Shell;
+
+const BASH: &str = r#"_git_status_tracker_hook() {
+    git rev-parse --is-inside-work-tree >/dev/null 2>&1 || return
+    local root
+    root="$(git rev-parse --show-toplevel)"
+    (git status --porcelain=v2 --branch 2>/dev/null | BIN put --path "$root" --porcelain-v2-stdin >/dev/null 2>&1 &)
+}
+if [[ "$PROMPT_COMMAND" != *_git_status_tracker_hook* ]]; then
+    PROMPT_COMMAND="_git_status_tracker_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+"#;
+
+const ZSH: &str = r#"_git_status_tracker_hook() {
+    git rev-parse --is-inside-work-tree >/dev/null 2>&1 || return
+    local root
+    root="$(git rev-parse --show-toplevel)"
+    { git status --porcelain=v2 --branch 2>/dev/null | BIN put --path "$root" --porcelain-v2-stdin >/dev/null 2>&1 } &!
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd _git_status_tracker_hook
+"#;
+
+const FISH: &str = r#"function __git_status_tracker_hook --on-event fish_prompt
+    git rev-parse --is-inside-work-tree >/dev/null 2>&1; or return
+    set -l root (git rev-parse --show-toplevel)
+    git status --porcelain=v2 --branch 2>/dev/null | command BIN put --path "$root" --porcelain-v2-stdin >/dev/null 2>&1 &
+    disown 2>/dev/null
+end
+"#;
+
+/// The hook for shell, calling the binary named bin.
+pub fn hook(shell: Shell, bin: &str) -> String {
+    let template = match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    };
+    template.replace("BIN", bin)
+}
@@ -1,14 +1,96 @@
-use std::collections::HashMap;
-use std::{error::Error, fs, thread, time};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, Read},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::Arc,
+    thread, time,
+};
 
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand, ValueHint};
 use serde::{Deserialize, Serialize};
 
+mod backup;
+mod bars;
+mod clock;
+mod color;
+mod compact;
+mod completions;
+mod config;
+mod daemon;
+mod delimited;
+mod doctor;
+mod events;
+mod export;
+mod git;
+mod history;
+mod http;
+mod icons;
+mod init;
+mod merge;
+mod migrations;
+mod pattern;
+mod porcelain;
+mod profiles;
+mod stats;
+mod store;
+mod template;
+mod toml;
+mod websocket;
+mod xdg;
+
+/// The exit code when a status is dirty, for --quiet.
+const EXIT_DIRTY: i32 = 1;
+
+/// The exit code when a path has no status.
+const EXIT_NOT_TRACKED: i32 = 2;
+
+/// The exit code for errors.
+const EXIT_ERROR: i32 = 3;
+
 #[derive(Parser, Debug)]
 #[clap(author = "Joshua Marsh <joshua.marshian@gmail.com>", version = "1.0", about = "store directory statuses for status bars", long_about = None)]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// When to color output.
+    #[clap(long, global = true, arg_enum, default_value = "auto")]
+    color: color::When,
+
+    /// The directory of the database, instead of
+    /// $XDG_DATA_HOME/git-status-tracker.
+    #[clap(long, global = true, env = "GIT_STATUS_TRACKER_DB", value_hint = ValueHint::DirPath)]
+    db: Option<String>,
+
+    /// The backend the database is kept in, instead of the config's
+    /// backend or sled.
+    #[clap(long, global = true, arg_enum)]
+    backend: Option<store::Kind>,
+
+    /// Open the database so it can't be written or lock out writers, as
+    /// subcommands that only read always do.
+    #[clap(long, global = true)]
+    read_only: bool,
+
+    /// The profile whose database to use, like work or personal. --db takes
+    /// precedence.
+    #[clap(long, global = true, env = "GST_PROFILE", default_value = profiles::DEFAULT)]
+    profile: String,
+
+    /// Fail straight away when another invocation holds the database
+    /// instead of waiting for it.
+    #[clap(long, global = true)]
+    no_wait: bool,
+
+    /// The socket of the daemon to use when it's running, and to listen on
+    /// for daemon and serve, instead of daemon.sock in the database
+    /// directory.
+    #[clap(long, global = true, env = "GST_SOCKET", value_hint = ValueHint::FilePath)]
+    socket: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -20,134 +102,2677 @@ enum Commands {
     Get(GetCommand),
 
     /// List all statuses in the database.
-    List,
+    List(ListCommand),
+
+    /// Delete a status from the database.
+    Delete(DeleteCommand),
+
+    /// Remove every status from the database.
+    Clear(ClearCommand),
+
+    /// Remove statuses whose paths no longer exist.
+    Prune(PruneCommand),
+
+    /// Move a status to a new path.
+    Rename(RenameCommand),
+
+    /// Exit successfully if a status exists for the path, and with 2 otherwise.
+    Exists(ExistsCommand),
+
+    /// Count the statuses in the database.
+    Count(CountCommand),
+
+    /// Search for statuses whose paths match.
+    Search(SearchCommand),
+
+    /// Find git repositories under a directory and put their statuses.
+    Scan(ScanCommand),
+
+    /// Recompute statuses in the database by running git.
+    Refresh(RefreshCommand),
+
+    /// Watch repositories and update their statuses when they change.
+    Watch(WatchCommand),
+
+    /// Hold the database open and answer put, get, list and delete from
+    /// other invocations over a unix socket.
+    Daemon(DaemonCommand),
+
+    /// Answer put, get, list and delete like daemon, on the socket set with
+    /// --socket, which invocations given the same socket use, and over HTTP
+    /// with --http.
+    Serve(ServeCommand),
+
+    /// Export every status in the database.
+    Export(ExportCommand),
+
+    /// Import statuses from an export.
+    Import(ImportCommand),
+
+    /// Show aggregate metrics across every status in the database.
+    Stats,
+
+    /// Show how a status has changed over time.
+    History(HistoryCommand),
+
+    /// Show what changed in a status between two points in its history.
+    Diff(DiffCommand),
+
+    /// Add tags to a status.
+    Tag(TagCommand),
+
+    /// Remove tags from a status.
+    Untag(TagCommand),
+
+    /// Print a shell completion script.
+    Completions(CompletionsCommand),
+
+    /// Check the database for problems.
+    Doctor(DoctorCommand),
+
+    /// Rewrite the database to reclaim unused space.
+    Compact,
+
+    /// Mark a status as updated now without changing it.
+    Touch(TouchCommand),
+
+    /// List statuses that haven't been updated recently.
+    Stale(StaleCommand),
+
+    /// Print a single line summarizing every status, for status bars.
+    Summary(SummaryCommand),
+
+    /// Print a shell hook that puts the current repository before each prompt.
+    Init(InitCommand),
+
+    /// Write everything in the database, history included, to a backup.
+    Backup(BackupCommand),
+
+    /// Load a backup into the database.
+    Restore(RestoreCommand),
+
+    /// Merge another database into this one, keeping the newest statuses.
+    Merge(MergeCommand),
+
+    /// Clear fields of a status.
+    Unset(UnsetCommand),
+
+    /// Rewrite statuses and history stored in an older layout in the
+    /// current one.
+    Migrate(MigrateCommand),
+
+    /// Manage profiles, the separate databases --profile picks.
+    Profiles(ProfilesCommand),
+}
+
+impl Commands {
+    /// Whether the subcommand only reads the database.
+    fn reads_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::Get(_)
+                | Commands::List(_)
+                | Commands::Summary(_)
+                | Commands::Exists(_)
+                | Commands::Count(_)
+                | Commands::Search(_)
+                | Commands::Stats
+                | Commands::History(_)
+                | Commands::Diff(_)
+                | Commands::Stale(_)
+                | Commands::Export(_)
+                | Commands::Backup(_)
+        ) || matches!(self, Commands::Restore(r) if r.list)
+    }
 }
 
 #[derive(Parser, Debug)]
 struct PutCommand {
     /// The path of the folder.
-    #[clap(short, long)]
-    path: String,
+    #[clap(short, long, value_hint = ValueHint::DirPath, required_unless_present = "stdin")]
+    path: Option<String>,
 
     /// The git branch, if any.
-    #[clap(short, long, default_value = "")]
-    branch: String,
+    #[clap(short, long)]
+    branch: Option<String>,
 
     /// The git status (--porcelain), if any.
-    #[clap(short, long, default_value = "")]
-    git_status: String,
+    #[clap(short, long)]
+    git_status: Option<String>,
+
+    /// The raw output of `git status --porcelain`, one `XY path` per line,
+    /// counted instead of --git-status.
+    #[clap(long, conflicts_with = "git-status")]
+    raw_status: Option<String>,
+
+    /// How many commits the branch is ahead of its upstream.
+    #[clap(long)]
+    ahead: Option<u64>,
+
+    /// How many commits the branch is behind its upstream.
+    #[clap(long)]
+    behind: Option<u64>,
+
+    /// How many stashes the repository has.
+    #[clap(long)]
+    stashes: Option<u64>,
+
+    /// The upstream the branch tracks, e.g. origin/main.
+    #[clap(short, long)]
+    upstream: Option<String>,
+
+    /// Metadata to attach as key=value, which may be given more than once.
+    #[clap(long, parse(try_from_str = parse_meta))]
+    meta: Vec<(String, String)>,
+
+    /// Read statuses from stdin, one per line, as JSON objects with path,
+    /// branch and git_status fields or as tab-separated values in that order.
+    #[clap(long, conflicts_with_all = &["path", "branch", "git-status", "raw-status", "ahead", "behind", "stashes", "upstream", "meta"])]
+    stdin: bool,
+
+    /// Read the raw output of `git status --porcelain` from stdin instead of
+    /// --git-status.
+    #[clap(long, conflicts_with_all = &["git-status", "raw-status", "stdin"])]
+    porcelain_stdin: bool,
+
+    /// Read the branch, upstream, ahead and behind counts and git status
+    /// from the output of `git status --porcelain=v2 --branch` on stdin.
+    #[clap(
+        long,
+        conflicts_with_all = &["git-status", "raw-status", "stdin", "porcelain-stdin"]
+    )]
+    porcelain_v2_stdin: bool,
+
+    /// With --auto, also count the modified and uninitialized submodules.
+    #[clap(long, requires = "auto")]
+    submodules: bool,
+
+    /// With --auto, also count ignored files, e.g. build artifacts.
+    #[clap(long, requires = "auto")]
+    include_ignored: bool,
+
+    /// Read the branch and status by running git in the folder.
+    #[clap(
+        short,
+        long,
+        conflicts_with_all = &["branch", "git-status", "raw-status", "ahead", "behind", "stashes", "upstream", "stdin", "porcelain-stdin", "porcelain-v2-stdin"]
+    )]
+    auto: bool,
+
+    /// Only change the fields that are given, keeping the rest of the stored
+    /// status.
+    #[clap(short, long, conflicts_with_all = &["stdin", "auto"])]
+    merge: bool,
+
+    /// Skip the write when the status hasn't changed, exiting with 1 when
+    /// nothing was written.
+    #[clap(short, long)]
+    if_changed: bool,
+
+    /// Drop the status when it isn't updated for this long, like 12h, 30d or
+    /// 8w, instead of after the config's ttl.
+    #[clap(long, parse(try_from_str = clock::parse_duration))]
+    ttl: Option<u64>,
+}
+
+impl PutCommand {
+    /// The statuses to put, with --ttl if it's given.
+    fn statuses(
+        &self,
+        find: impl FnMut(&str) -> Result<Option<Status>, Box<dyn Error>>,
+    ) -> Result<Vec<Status>, Box<dyn Error>> {
+        let mut statuses = self.read(find)?;
+        if self.ttl.is_some() {
+            for status in &mut statuses {
+                status.ttl = self.ttl;
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// The statuses to put, read from stdin when --stdin is given. find looks
+    /// up the stored status that --merge starts from.
+    fn read(
+        &self,
+        mut find: impl FnMut(&str) -> Result<Option<Status>, Box<dyn Error>>,
+    ) -> Result<Vec<Status>, Box<dyn Error>> {
+        let path = normalize_path(self.path.as_deref().unwrap_or_default());
+        if self.auto {
+            let options = git::Options {
+                submodules: self.submodules,
+                ignored: self.include_ignored,
+            };
+            let mut status = git::read(Path::new(&path), &options)?;
+            status.path = path;
+            status.metadata.extend(self.meta.iter().cloned());
+            return Ok(vec![status]);
+        }
+        if !self.stdin {
+            let mut status = if self.merge {
+                find(&path)?.unwrap_or_default()
+            } else {
+                Status::default()
+            };
+            status.path = path;
+            if self.porcelain_stdin {
+                let mut porcelain = String::new();
+                io::stdin().lock().read_to_string(&mut porcelain)?;
+                status.git_status = git::count_porcelain(&porcelain);
+                status.categories = git::categorize_porcelain(&porcelain);
+            } else if self.porcelain_v2_stdin {
+                let mut porcelain = String::new();
+                io::stdin().lock().read_to_string(&mut porcelain)?;
+                let read = git::parse_porcelain_v2(&porcelain);
+                status.branch = read.branch;
+                status.upstream = read.upstream;
+                status.ahead = read.ahead;
+                status.behind = read.behind;
+                status.git_status = read.git_status;
+                status.categories = read.categories;
+                status.head_state = read.head_state;
+            } else if let Some(raw_status) = &self.raw_status {
+                status.git_status = git::count_porcelain(raw_status);
+                status.categories = git::categorize_porcelain(raw_status);
+            } else if let Some(git_status) = &self.git_status {
+                status.set_counts(parse_counts(git_status.trim())?);
+            }
+            if let Some(branch) = &self.branch {
+                status.branch = branch.trim().to_string();
+            }
+            if let Some(upstream) = &self.upstream {
+                status.upstream = Some(upstream.clone());
+            }
+            status.ahead = self.ahead.unwrap_or(status.ahead);
+            status.behind = self.behind.unwrap_or(status.behind);
+            status.stashes = self.stashes.unwrap_or(status.stashes);
+            status.metadata.extend(self.meta.iter().cloned());
+            return Ok(vec![status]);
+        }
+        let mut statuses = Vec::new();
+        for (number, line) in io::stdin().lock().lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let status =
+                PutRecord::parse(&line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+}
+
+/// Parse metadata given as key=value.
+fn parse_meta(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid metadata {}, expected key=value", s)),
+    }
+}
+
+/// A status read by put --stdin, either a JSON object or tab-separated path,
+/// branch and git status.
+#[derive(Deserialize)]
+struct PutRecord {
+    path: String,
+    #[serde(default)]
+    branch: String,
+    #[serde(default)]
+    git_status: GitStatusRecord,
+    #[serde(default)]
+    ahead: u64,
+    #[serde(default)]
+    behind: u64,
+    #[serde(default)]
+    stashes: u64,
+    #[serde(default)]
+    upstream: Option<String>,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+/// The git status of a record, either in the --git-status format or as a
+/// map of codes to counts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GitStatusRecord {
+    Packed(String),
+    Counts(BTreeMap<String, u64>),
+}
+
+impl Default for GitStatusRecord {
+    fn default() -> GitStatusRecord {
+        GitStatusRecord::Packed(String::new())
+    }
+}
+
+impl PutRecord {
+    fn parse(line: &str) -> Result<Status, Box<dyn Error>> {
+        let record = if line.trim_start().starts_with('{') {
+            serde_json::from_str(line)?
+        } else {
+            let mut fields = line.splitn(3, '\t');
+            PutRecord {
+                path: fields.next().unwrap_or_default().to_string(),
+                branch: fields.next().unwrap_or_default().to_string(),
+                git_status: GitStatusRecord::Packed(fields.next().unwrap_or_default().to_string()),
+                ahead: 0,
+                behind: 0,
+                stashes: 0,
+                upstream: None,
+                metadata: BTreeMap::new(),
+            }
+        };
+        let path = normalize_path(&record.path);
+        if path.is_empty() {
+            return Err("missing path".into());
+        }
+        let mut status = match record.git_status {
+            GitStatusRecord::Packed(git_status) => {
+                Status::new(&path, record.branch.trim(), git_status.trim())?
+            }
+            GitStatusRecord::Counts(git_status) => Status {
+                path,
+                branch: record.branch.trim().to_string(),
+                categories: Categories::guess(&git_status),
+                git_status,
+                ..Default::default()
+            },
+        };
+        status.ahead = record.ahead;
+        status.behind = record.behind;
+        status.stashes = record.stashes;
+        status.upstream = record.upstream;
+        status.metadata = record.metadata;
+        Ok(status)
+    }
 }
 
 #[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("output")))]
 struct GetCommand {
-    /// The path of the folder.
+    /// The path of the folder, which may be given more than once.
+    #[clap(short, long, value_hint = ValueHint::DirPath, required_unless_present = "stdin")]
+    path: Vec<String>,
+
+    /// Read the paths from stdin, one per line.
+    #[clap(long, conflicts_with = "path")]
+    stdin: bool,
+
+    /// The line to print for paths that have no status, instead of failing.
     #[clap(short, long)]
-    path: String,
+    default: Option<String>,
+
+    /// Print an empty line for paths that have no status, instead of failing.
+    #[clap(long, conflicts_with = "default")]
+    default_empty: bool,
+
+    /// Use the status of the nearest tracked parent when the path itself
+    /// isn't tracked.
+    #[clap(short, long)]
+    ancestor: bool,
+
+    /// Show when the status was created and updated, like 5m ago.
+    #[clap(long)]
+    show_times: bool,
+
+    /// Print only this field: commit, git-status, git-status.CODE, or any
+    /// placeholder --format accepts, like branch or total.
+    #[clap(short, long, group = "output")]
+    field: Option<Selector>,
+
+    /// Print the status on one line laid out by a template, e.g.
+    /// '{branch} {modified}M {untracked}?'.
+    #[clap(long, group = "output")]
+    format: Option<String>,
+
+    /// Lay out the status with a named template from the templates
+    /// directory, e.g. waybar for templates/waybar.tmpl.
+    #[clap(long, group = "output")]
+    template: Option<String>,
+
+    /// Print each status as a JSON object on its own line.
+    #[clap(short, long, group = "output", conflicts_with = "show-times")]
+    json: bool,
+
+    /// Print the status on one line with icons for the branch and codes.
+    #[clap(short, long, group = "output")]
+    icons: bool,
+
+    /// Print only the metadata with this key.
+    #[clap(long, group = "output")]
+    meta: Option<String>,
+
+    /// Print the JSON a waybar custom module reads.
+    #[clap(long, group = "output")]
+    waybar: bool,
+
+    /// Print a line with polybar format tags for a custom/script module.
+    #[clap(long, group = "output")]
+    polybar: bool,
+
+    /// Print the full text, short text and color lines i3blocks reads.
+    #[clap(long, group = "output")]
+    i3blocks: bool,
+
+    /// Print a segment with tmux styles for status-right.
+    #[clap(long, group = "output")]
+    tmux: bool,
+
+    /// Print xmobar markup for a Run Com command.
+    #[clap(long, group = "output")]
+    xmobar: bool,
+
+    /// Print the stable tab-separated format in this version, 1 by default,
+    /// for scripts.
+    #[clap(
+        long,
+        group = "output",
+        min_values = 0,
+        default_missing_value = "1",
+        parse(try_from_str = porcelain::version)
+    )]
+    porcelain: Option<u32>,
+
+    /// Print compact symbols for a starship custom module, exiting with 1
+    /// when the path has no status so the module hides.
+    #[clap(long, group = "output", conflicts_with_all = &["default", "default-empty"])]
+    starship: bool,
+
+    /// End each record with a NUL instead of a newline, for xargs -0.
+    #[clap(short = '0', long)]
+    null: bool,
+
+    /// Print nothing, exiting with 0 when every status is clean, 1 when any
+    /// is dirty and 2 when any path has no status.
+    #[clap(short, long, conflicts_with_all = &["default", "default-empty"])]
+    quiet: bool,
+
+    /// Cut branches longer than this many characters in --tmux output.
+    #[clap(long, requires = "tmux")]
+    max_branch: Option<usize>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct Status {
-    path: String,
-    branch: String,
-    git_status: HashMap<String, u64>,
+/// A field of a status that get can print on its own.
+#[derive(Debug)]
+enum Selector {
+    Commit,
+    /// The name and URL of the remote.
+    Remote,
+    /// The counts of every code, or of a single code.
+    GitStatus(Option<String>),
+    /// Anything a --format template can hold.
+    Placeholder(String),
 }
 
-impl Status {
-    fn new(path: &str, branch: &str, git_status: &str) -> Status {
-        Status {
-            path: path.to_string(),
-            branch: branch.to_string(),
-            git_status: git_status
-                .split('|')
-                .filter(|s| !s.is_empty())
-                .map(|s| {
-                    let parts = s.split(' ').collect::<Vec<&str>>();
-                    (parts[1].to_string(), parts[0].parse::<u64>().unwrap())
-                })
-                .collect::<HashMap<String, u64>>(),
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Selector, String> {
+        match s.split_once('.') {
+            _ if s == "commit" => Ok(Selector::Commit),
+            _ if s == "remote" => Ok(Selector::Remote),
+            _ if s == "git-status" => Ok(Selector::GitStatus(None)),
+            Some(("git-status", code)) => Ok(Selector::GitStatus(Some(code.to_string()))),
+            _ => template::placeholder(&Status::default(), s)
+                .map(|_| Selector::Placeholder(s.to_string()))
+                .map_err(|_| format!("invalid field {}", s)),
         }
     }
 }
 
-struct Database {
-    db: sled::Db,
+impl Selector {
+    fn value(&self, status: &Status) -> String {
+        match self {
+            Selector::Commit => status
+                .commit
+                .as_ref()
+                .map(|c| format!("{} {} {}", c.hash, clock::format(c.date), c.subject))
+                .unwrap_or_default(),
+            Selector::Remote => status
+                .remote
+                .as_ref()
+                .map(|r| format!("{} {}", r.name, r.url))
+                .unwrap_or_default(),
+            Selector::GitStatus(None) => status.counts(),
+            Selector::GitStatus(Some(code)) => status
+                .git_status
+                .get(code)
+                .copied()
+                .unwrap_or(0)
+                .to_string(),
+            Selector::Placeholder(name) => template::placeholder(status, name).unwrap_or_default(),
+        }
+    }
 }
 
-impl Database {
-    fn new(path: &str) -> Result<Database, Box<dyn Error>> {
-        let mut attempts = 0;
-        loop {
-            match sled::open(path) {
-                Ok(db) => return Ok(Database { db }),
-                Err(e) => {
-                    attempts += 1;
-                    if attempts > 10 {
-                        return Err(format!("failed after 10 attempts: {}", e).into());
+impl GetCommand {
+    fn paths(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.stdin {
+            return Ok(self.path.iter().map(|p| normalize_path(p)).collect());
+        }
+        let mut paths = Vec::new();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                paths.push(normalize_path(&line));
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Print the statuses, prefixed by their path when there's more than one,
+    /// returning the code to exit with. Without a default, paths that have no
+    /// status are reported on stderr and the code is 2 once the rest are
+    /// printed, or 1 and quietly for --starship. With --quiet only the code
+    /// is set.
+    fn print(
+        &self,
+        mut find: impl FnMut(&str) -> Result<Option<Status>, Box<dyn Error>>,
+        palette: &color::Palette,
+        config: &config::Config,
+    ) -> Result<i32, Box<dyn Error>> {
+        let paths = self.paths()?;
+        let default = match (&self.default, self.default_empty) {
+            (Some(default), _) => Some(default.as_str()),
+            (None, true) => Some(""),
+            (None, false) => None,
+        };
+        let meta = self
+            .meta
+            .as_ref()
+            .map(|key| Selector::Placeholder(format!("meta:{}", key)));
+        let field = self.field.as_ref().or(meta.as_ref());
+        let (mut missing, mut dirty) = (false, false);
+        for path in &paths {
+            let mut status = find(path)?;
+            if self.ancestor {
+                for parent in Path::new(path).ancestors().skip(1) {
+                    if status.is_some() {
+                        break;
                     }
+                    status = find(&normalize_path(&parent.to_string_lossy()))?;
+                }
+            }
+            if self.quiet {
+                match status {
+                    Some(status) => dirty |= status.is_dirty(),
+                    None => missing = true,
+                }
+                continue;
+            }
+            if status.is_none() && default.is_none() {
+                if !self.starship {
+                    eprintln!("no status found for {}", path);
+                }
+                missing = true;
+                continue;
+            }
+            let end = if self.null { '\0' } else { '\n' };
+            if self.json {
+                match status {
+                    Some(status) => print!("{}{}", serde_json::to_string(&status)?, end),
+                    None => print!("{}{}", default.unwrap_or_default(), end),
                 }
+                continue;
+            }
+            if paths.len() > 1 {
+                print!("{}{}", path, end);
             }
-            thread::sleep(time::Duration::from_millis(100))
+            let status = match status {
+                Some(status) => status,
+                None => {
+                    print!("{}{}", default.unwrap_or_default(), end);
+                    continue;
+                }
+            };
+            let threshold = palette.threshold();
+            let output = if let Some(field) = field {
+                field.value(&status)
+            } else if let Some(format) = &self.format {
+                template::render(format, &status)?
+            } else if self.waybar {
+                bars::waybar(&status, threshold)?
+            } else if self.polybar {
+                let (icons, polybar) = (&config.icons, &config.polybar);
+                bars::polybar(&status, icons, polybar, &config.bar, threshold)
+            } else if self.i3blocks {
+                bars::i3blocks(&status, &config.bar, threshold)
+            } else if self.tmux {
+                bars::tmux(&status, &config.bar, threshold, self.max_branch)
+            } else if self.xmobar {
+                bars::xmobar(&status, &config.xmobar, &config.bar, threshold)
+            } else if let Some(version) = self.porcelain {
+                porcelain::line(&status, version)
+            } else if self.starship {
+                bars::starship(&status)
+            } else if self.icons {
+                palette.paint(status.changes(), &config.icons.render(&status))
+            } else {
+                status.describe(self.show_times, palette)
+            };
+            print!("{}{}", output, end);
+        }
+        if missing {
+            return Ok(if self.starship { 1 } else { EXIT_NOT_TRACKED });
         }
+        if dirty {
+            return Ok(EXIT_DIRTY);
+        }
+        Ok(0)
     }
+}
 
-    fn update(self, status: Status) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.insert(
-            bincode::serialize(&status.path)?,
-            bincode::serialize(&status)?,
-        )?;
-        self.db.flush()?;
-        Ok(())
-    }
+#[derive(Parser, Debug)]
+struct ListCommand {
+    /// Only list statuses with this tag.
+    #[clap(short, long)]
+    tag: Option<String>,
 
-    fn get(self, path: &str) -> Result<Status, Box<dyn std::error::Error>> {
-        Ok(bincode::deserialize(
-            &self.db.get(bincode::serialize(path)?)?.unwrap_or_default(),
-        )?)
-    }
-}
+    /// Only list statuses with merge conflicts.
+    #[clap(long)]
+    conflicted: bool,
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Create our data directory if it doesn't exist
-    let dir = dirs::home_dir()
-        .unwrap()
-        .join(".config")
-        .join("git-status-tracker");
-    fs::create_dir_all(dir.clone())?;
+    /// Only list statuses whose remote URL contains this, e.g.
+    /// github.com/myorg. SSH URLs like git@host:org/repo match host/org.
+    #[clap(long)]
+    remote: Option<String>,
 
-    let db = Database::new(&dir.into_os_string().into_string().unwrap())?;
+    /// Show when each status was created and updated, like 5m ago.
+    #[clap(long)]
+    show_times: bool,
 
-    let cli = Cli::parse();
-    match &cli.command {
-        Commands::List => {
-            for r in db.db.iter() {
-                let (_, v) = r?;
-                let status: Status = bincode::deserialize(&v)?;
-                println!("{}: {} {:?}", status.path, status.branch, status.git_status);
-            }
+    /// Print the statuses as a JSON array.
+    #[clap(short, long, conflicts_with = "show-times")]
+    json: bool,
+
+    /// Print each status as a JSON object on its own line.
+    #[clap(long, conflicts_with_all = &["show-times", "json"])]
+    json_lines: bool,
+
+    /// Print the statuses as a table with a header row.
+    #[clap(short, long, arg_enum, conflicts_with_all = &["show-times", "json", "json-lines"])]
+    format: Option<delimited::Format>,
+
+    /// Lay out each status with a named template from the templates
+    /// directory, e.g. table for templates/table.tmpl.
+    #[clap(long, conflicts_with_all = &["show-times", "json", "json-lines", "format"])]
+    template: Option<String>,
+
+    /// Print a JSON object keyed by path with the text and class of each
+    /// status, for eww widgets.
+    #[clap(
+        long,
+        conflicts_with_all = &["show-times", "json", "json-lines", "format", "template"]
+    )]
+    eww: bool,
+
+    /// Show how many submodules are modified and uninitialized.
+    #[clap(long)]
+    with_submodules: bool,
+
+    /// Show the worktrees of each repository together under its main
+    /// worktree.
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "json-lines", "format", "template", "eww", "porcelain"]
+    )]
+    group_by_repo: bool,
+
+    /// Print the stable tab-separated format in this version, 1 by default,
+    /// for scripts.
+    #[clap(
+        long,
+        min_values = 0,
+        default_missing_value = "1",
+        parse(try_from_str = porcelain::version),
+        conflicts_with_all = &["show-times", "json", "json-lines", "format", "template", "eww"]
+    )]
+    porcelain: Option<u32>,
+
+    /// End each status with a NUL instead of a newline, for xargs -0.
+    #[clap(short = '0', long, conflicts_with_all = &["json", "format", "eww"])]
+    null: bool,
+
+    /// The contents of the template.
+    #[clap(skip)]
+    layout: Option<String>,
+}
+
+impl ListCommand {
+    fn print(&self, statuses: Vec<Status>, palette: &color::Palette) -> Result<(), Box<dyn Error>> {
+        let mut statuses = statuses
+            .into_iter()
+            .filter(|s| s.has_tag(&self.tag))
+            .filter(|s| s.has_remote(&self.remote))
+            .filter(|s| !self.conflicted || s.conflicts() > 0)
+            .collect::<Vec<_>>();
+        if self.group_by_repo {
+            statuses.sort_by(|x, y| (x.repo(), &x.path).cmp(&(y.repo(), &y.path)));
         }
-        Commands::Put(p) => {
-            let path = p
-                .path
-                .trim()
-                .strip_suffix('/')
-                .unwrap_or(p.path.trim())
-                .to_string();
-            let status = Status::new(&path, p.branch.trim(), p.git_status.trim());
-            db.update(status)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&statuses)?);
+            return Ok(());
         }
-        Commands::Get(g) => {
-            let status = db.get(&g.path)?;
-            println!("{}", status.branch.trim());
-            let mut statuses = status.git_status.into_iter().collect::<Vec<_>>();
-            statuses.sort_by(|x, y| x.0.cmp(&y.0));
-            for (i, (k, v)) in statuses.iter().enumerate() {
-                if i > 0 {
-                    print!("| ");
+        if self.eww {
+            println!("{}", bars::eww(&statuses)?);
+            return Ok(());
+        }
+        if let Some(format) = self.format {
+            print!("{}", delimited::to_string(&statuses, format));
+            return Ok(());
+        }
+        let end = if self.null { '\0' } else { '\n' };
+        let mut repo = None;
+        for status in &statuses {
+            if self.group_by_repo {
+                if repo != Some(status.repo()) {
+                    print!("{}{}", status.repo(), end);
+                    repo = Some(status.repo());
                 }
-                print!("{} {} ", v, k);
+                print!("  ");
             }
-            println!();
+            let line = if let Some(layout) = &self.layout {
+                template::render(layout, status)?
+            } else if self.json_lines {
+                serde_json::to_string(status)?
+            } else if let Some(version) = self.porcelain {
+                porcelain::line(status, version)
+            } else if let (true, Some(submodules)) = (self.with_submodules, &status.submodules) {
+                format!(
+                    "{} submodules: {} modified, {} uninitialized",
+                    status.line(self.show_times, palette),
+                    submodules.modified,
+                    submodules.uninitialized
+                )
+            } else {
+                status.line(self.show_times, palette)
+            };
+            print!("{}{}", line, end);
         }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+struct DeleteCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+}
+
+#[derive(Parser, Debug)]
+struct ClearCommand {
+    /// Confirm that all statuses should be removed.
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Parser, Debug)]
+struct DaemonCommand {
+    /// Exit after this long without a request, like 5m, and step aside for
+    /// invocations that need the database themselves. Brokers are started
+    /// with it.
+    #[clap(long, parse(try_from_str = clock::parse_duration))]
+    idle: Option<u64>,
+}
+
+#[derive(Parser, Debug)]
+struct ServeCommand {
+    /// Also serve a JSON API over HTTP on this address, like
+    /// 127.0.0.1:7979.
+    #[clap(long)]
+    http: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct PruneCommand {
+    /// Only show which statuses would be removed.
+    #[clap(short, long)]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+struct RenameCommand {
+    /// The current path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    from: String,
+
+    /// The new path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    to: String,
+}
+
+#[derive(Parser, Debug)]
+struct ExistsCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+}
+
+#[derive(Parser, Debug)]
+struct CountCommand {
+    /// Only count statuses with uncommitted changes.
+    #[clap(short, long)]
+    dirty: bool,
+
+    /// Only count statuses on this branch.
+    #[clap(short, long)]
+    branch: Option<String>,
+
+    /// Only count statuses with this tag.
+    #[clap(short, long)]
+    tag: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("matcher").required(true)))]
+struct SearchCommand {
+    /// A regular expression the path must match.
+    #[clap(long, group = "matcher")]
+    pattern: Option<String>,
+
+    /// A substring the path must contain.
+    #[clap(short, long, group = "matcher")]
+    contains: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ScanCommand {
+    /// The directory to search for repositories.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    root: String,
+
+    /// How many directories deep to search, unlimited by default.
+    #[clap(short, long)]
+    max_depth: Option<usize>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("target").required(true)))]
+struct RefreshCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath, group = "target")]
+    path: Option<String>,
+
+    /// Refresh every status in the database.
+    #[clap(short, long, group = "target")]
+    all: bool,
+
+    /// Also count the modified and uninitialized submodules.
+    #[clap(long)]
+    submodules: bool,
+
+    /// Also count ignored files, e.g. build artifacts.
+    #[clap(long)]
+    include_ignored: bool,
+}
+
+#[derive(Parser, Debug)]
+struct WatchCommand {
+    /// Watch the repositories under this directory instead of the tracked ones.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    root: Option<String>,
+
+    /// How many seconds to wait between checks.
+    #[clap(short, long, default_value = "2")]
+    interval: u64,
+}
+
+#[derive(Parser, Debug)]
+struct ExportCommand {
+    /// The format to write.
+    #[clap(short, long, arg_enum, default_value = "json")]
+    format: export::Format,
+
+    /// The file to write to instead of stdout.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    output: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("mode")))]
+struct ImportCommand {
+    /// The file to read.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    input: String,
+
+    /// The format to read, guessed from the file extension by default.
+    #[clap(short, long, arg_enum)]
+    format: Option<export::Format>,
+
+    /// Keep statuses that aren't in the import (the default).
+    #[clap(short, long, group = "mode")]
+    merge: bool,
+
+    /// Remove statuses that aren't in the import.
+    #[clap(short, long, group = "mode")]
+    replace: bool,
+}
+
+#[derive(Parser, Debug)]
+struct HistoryCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+
+    /// Only show this many of the most recent entries.
+    #[clap(short, long)]
+    limit: Option<usize>,
+}
+
+#[derive(Parser, Debug)]
+struct DiffCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+
+    /// Compare from the status at this time (seconds since the epoch, an
+    /// RFC 3339 date, or a duration ago like 6h), by default the previous one.
+    #[clap(short, long)]
+    from: Option<String>,
+
+    /// Compare to the status at this time, by default the current one.
+    #[clap(short, long)]
+    to: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct TagCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+
+    /// The tag, which may be given more than once.
+    #[clap(short, long, required = true)]
+    tag: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct CompletionsCommand {
+    /// The shell to complete for.
+    #[clap(arg_enum)]
+    shell: completions::Shell,
+}
+
+#[derive(Parser, Debug)]
+struct DoctorCommand {
+    /// Repair any problems that are found, setting aside values that can't
+    /// be read.
+    #[clap(short, long)]
+    fix: bool,
+
+    /// Rewrite the whole database from whatever can be read, after setting
+    /// the rest aside.
+    #[clap(long)]
+    rebuild: bool,
+}
+
+#[derive(Parser, Debug)]
+struct TouchCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+}
+
+#[derive(Parser, Debug)]
+struct StaleCommand {
+    /// How long ago a status must have been updated, like 12h, 7d or 2w.
+    #[clap(short, long, default_value = "7d", parse(try_from_str = clock::parse_duration))]
+    older_than: u64,
+}
+
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("output")))]
+struct SummaryCommand {
+    /// Only summarize statuses with this tag.
+    #[clap(short, long)]
+    tag: Option<String>,
+
+    /// The icon to start the line with, if any.
+    #[clap(short, long, default_value = "🌿")]
+    icon: String,
+
+    /// The separator between counts.
+    #[clap(short, long, default_value = " · ")]
+    separator: String,
+
+    /// Leave out the labels after the counts.
+    #[clap(short, long)]
+    compact: bool,
+
+    /// Print the JSON a waybar custom module reads.
+    #[clap(long, group = "output")]
+    waybar: bool,
+
+    /// Print a line with polybar format tags for a custom/script module.
+    #[clap(long, group = "output")]
+    polybar: bool,
+
+    /// Print the full text, short text and color lines i3blocks reads.
+    #[clap(long, group = "output")]
+    i3blocks: bool,
+
+    /// Print a segment with tmux styles for status-right.
+    #[clap(long, group = "output")]
+    tmux: bool,
+
+    /// Print xmobar markup for a Run Com command.
+    #[clap(long, group = "output")]
+    xmobar: bool,
+
+    /// Print nothing, exiting with 0 when every status is clean and 1 when
+    /// any is dirty.
+    #[clap(short, long)]
+    quiet: bool,
+}
+
+impl SummaryCommand {
+    /// Print the summary, returning the code to exit with.
+    fn print(
+        &self,
+        statuses: Vec<Status>,
+        palette: &color::Palette,
+        config: &config::Config,
+    ) -> Result<i32, Box<dyn Error>> {
+        let statuses = statuses
+            .into_iter()
+            .filter(|s| s.has_tag(&self.tag))
+            .collect::<Vec<_>>();
+        let style = stats::SummaryStyle {
+            icon: &self.icon,
+            separator: &self.separator,
+            compact: self.compact,
+        };
+        if self.quiet {
+            if statuses.iter().any(|s| s.is_dirty()) {
+                return Ok(EXIT_DIRTY);
+            }
+            return Ok(0);
+        }
+        if self.waybar {
+            println!("{}", bars::waybar_summary(&statuses, &style)?);
+            return Ok(0);
+        }
+        if self.polybar {
+            println!(
+                "{}",
+                bars::polybar_summary(&statuses, &style, &config.bar, palette.threshold())
+            );
+            return Ok(0);
+        }
+        if self.i3blocks {
+            println!(
+                "{}",
+                bars::i3blocks_summary(&statuses, &style, &config.bar, palette.threshold())
+            );
+            return Ok(0);
+        }
+        if self.tmux {
+            println!(
+                "{}",
+                bars::tmux_summary(&statuses, &style, &config.bar, palette.threshold())
+            );
+            return Ok(0);
+        }
+        if self.xmobar {
+            let (xmobar, colors) = (&config.xmobar, &config.bar);
+            println!(
+                "{}",
+                bars::xmobar_summary(&statuses, &style, xmobar, colors, palette.threshold())
+            );
+            return Ok(0);
+        }
+        let stats = stats::Stats::new(&statuses);
+        println!("{}", palette.paint(stats.changes, &stats.summary(&style)));
+        Ok(0)
+    }
+}
+
+#[derive(Parser, Debug)]
+struct InitCommand {
+    /// The shell to hook into.
+    #[clap(arg_enum)]
+    shell: completions::Shell,
+}
+
+#[derive(Parser, Debug)]
+struct BackupCommand {
+    /// The file to write.
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct RestoreCommand {
+    /// The backup to read.
+    #[clap(short, long, value_hint = ValueHint::FilePath, required_unless_present = "list")]
+    input: Option<String>,
+
+    /// List the backups taken as statuses were put instead, oldest first.
+    #[clap(short, long, conflicts_with_all = &["input", "merge"])]
+    list: bool,
+
+    /// Keep what's already in the database instead of replacing it.
+    #[clap(short, long)]
+    merge: bool,
+}
+
+#[derive(Parser, Debug)]
+struct MergeCommand {
+    /// The directory of the other database.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    other: String,
+}
+
+#[derive(Parser, Debug)]
+struct ProfilesCommand {
+    #[clap(subcommand)]
+    action: ProfilesAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfilesAction {
+    /// List the profiles, marking the one in use with *.
+    List,
+}
+
+#[derive(Parser, Debug)]
+struct MigrateCommand {
+    /// Only count what would be rewritten.
+    #[clap(short, long)]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+struct UnsetCommand {
+    /// The path of the folder.
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    path: String,
+
+    /// The field to clear: branch, git-status, git-status:CODE for a single
+    /// code, or meta:KEY. May be given more than once.
+    #[clap(short, long, required = true)]
+    field: Vec<Field>,
+}
+
+/// A field of a status that can be cleared.
+#[derive(Debug)]
+enum Field {
+    Branch,
+    /// One code of the git status, or all of them.
+    GitStatus(Option<String>),
+    /// One metadata key.
+    Meta(String),
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Field, String> {
+        match s.split_once(':') {
+            None if s == "branch" => Ok(Field::Branch),
+            None if s == "git-status" => Ok(Field::GitStatus(None)),
+            Some(("git-status", code)) if !code.is_empty() => {
+                Ok(Field::GitStatus(Some(code.to_string())))
+            }
+            Some(("meta", key)) if !key.is_empty() => Ok(Field::Meta(key.to_string())),
+            _ => Err(format!(
+                "invalid field {}, expected branch, git-status, git-status:CODE or meta:KEY",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+struct Status {
+    path: String,
+    branch: String,
+    git_status: BTreeMap<String, u64>,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    /// When the status was first put, in seconds since the epoch.
+    #[serde(default)]
+    created_at: u64,
+    /// When the status was last put, in seconds since the epoch.
+    #[serde(default)]
+    updated_at: u64,
+    /// How many commits the branch is ahead of its upstream.
+    #[serde(default)]
+    ahead: u64,
+    /// How many commits the branch is behind its upstream.
+    #[serde(default)]
+    behind: u64,
+    /// How many stashes the repository has.
+    #[serde(default)]
+    stashes: u64,
+    /// The upstream the branch tracks, e.g. `origin/main`, or none for a
+    /// local-only branch.
+    #[serde(default)]
+    upstream: Option<String>,
+    /// The last commit on the branch, if there is one.
+    #[serde(default)]
+    commit: Option<Commit>,
+    /// Free-form annotations like the project owner, kept across puts.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+    /// Whether HEAD is on a branch. When it's detached the branch holds the
+    /// nearest tag, like `v1.2.3~2`, or the short commit, like `@abc1234`.
+    #[serde(default)]
+    head_state: HeadState,
+    /// The operation in progress, like `REBASE 2/5` or `MERGING`, if any.
+    #[serde(default)]
+    operation: Option<String>,
+    /// How many submodules are dirty or uninitialized, when they were
+    /// checked.
+    #[serde(default)]
+    submodules: Option<Submodules>,
+    /// The main worktree of the repository, shared by all its worktrees.
+    #[serde(default)]
+    repo_id: Option<String>,
+    /// Whether the repository is bare, with no worktree to have a status.
+    #[serde(default)]
+    bare: bool,
+    /// The remote the branch tracks, or origin, or else the first remote.
+    #[serde(default)]
+    remote: Option<Remote>,
+    /// The changes sorted into semantic buckets.
+    #[serde(default)]
+    categories: Categories,
+    /// Whether the clone has only part of the history.
+    #[serde(default)]
+    shallow: bool,
+    /// Whether only some files are checked out, so the counts only cover
+    /// those.
+    #[serde(default)]
+    sparse: bool,
+    /// How long after its last update the status expires, in seconds,
+    /// instead of the config's ttl.
+    #[serde(default)]
+    ttl: Option<u64>,
+}
+
+/// Changes sorted by what they mean rather than by their XY code. A change
+/// can be in more than one bucket, e.g. a staged rename is both staged and
+/// renamed and `AM` is both added and modified, but conflicts are only ever
+/// conflicted and untracked and ignored files are only those.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Categories {
+    staged: u64,
+    unstaged: u64,
+    untracked: u64,
+    ignored: u64,
+    conflicted: u64,
+    /// Modified, or with a type change.
+    modified: u64,
+    added: u64,
+    deleted: u64,
+    renamed: u64,
+    copied: u64,
+}
+
+impl Categories {
+    /// Add count changes with the porcelain XY code, where X is the index and
+    /// Y the worktree.
+    fn add(&mut self, xy: &str, count: u64) {
+        match xy {
+            "??" => self.untracked += count,
+            "!!" => self.ignored += count,
+            "DD" | "AU" | "UD" | "UA" | "DU" | "AA" | "UU" => self.conflicted += count,
+            _ => {
+                let mut chars = xy.chars().map(|c| if c == '.' { ' ' } else { c });
+                let (x, y) = (chars.next().unwrap_or(' '), chars.next().unwrap_or(' '));
+                let either = |codes: &[char]| codes.contains(&x) || codes.contains(&y);
+                for (bucket, codes) in [
+                    (&mut self.modified, &['M', 'T'][..]),
+                    (&mut self.added, &['A']),
+                    (&mut self.deleted, &['D']),
+                    (&mut self.renamed, &['R']),
+                    (&mut self.copied, &['C']),
+                ] {
+                    if either(codes) {
+                        *bucket += count;
+                    }
+                }
+                if x != ' ' {
+                    self.staged += count;
+                }
+                if y != ' ' {
+                    self.unstaged += count;
+                }
+            }
+        }
+    }
+
+    /// The count in the bucket called name, as templates and tables name
+    /// them.
+    fn get(&self, name: &str) -> Option<u64> {
+        Some(match name {
+            "staged" => self.staged,
+            "unstaged" => self.unstaged,
+            "untracked" => self.untracked,
+            "ignored" => self.ignored,
+            "conflicted" => self.conflicted,
+            "modified" => self.modified,
+            "added" => self.added,
+            "deleted" => self.deleted,
+            "renamed" => self.renamed,
+            "copied" => self.copied,
+            _ => return None,
+        })
+    }
+
+    /// Sort counts whose codes have lost their spaces, as in the --git-status
+    /// format, into buckets. A single letter can't say whether the change is
+    /// staged, so additions, renames and copies are taken as staged and the
+    /// rest as unstaged, other than U, which is a conflict.
+    fn guess(counts: &BTreeMap<String, u64>) -> Categories {
+        let mut categories = Categories::default();
+        for (code, count) in counts {
+            match code.as_str() {
+                "U" => categories.add("UU", *count),
+                "A" | "R" | "C" => categories.add(&format!("{} ", code), *count),
+                code if code.len() == 1 => categories.add(&format!(" {}", code), *count),
+                code => categories.add(code, *count),
+            }
+        }
+        categories
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Remote {
+    name: String,
+    url: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Submodules {
+    /// Submodules with a different commit checked out, changes or untracked
+    /// files.
+    modified: u64,
+    uninitialized: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HeadState {
+    #[default]
+    Branch,
+    Detached,
+    /// On a branch without any commits yet.
+    Unborn,
+}
+
+impl HeadState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HeadState::Branch => "branch",
+            HeadState::Detached => "detached",
+            HeadState::Unborn => "unborn",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Commit {
+    /// The abbreviated hash.
+    hash: String,
+    /// The author date in seconds since the epoch.
+    date: u64,
+    subject: String,
+}
+
+/// Parse a git status in the `N CODE|N CODE` format put takes.
+fn parse_counts(git_status: &str) -> Result<BTreeMap<String, u64>, String> {
+    let mut counts = BTreeMap::new();
+    for s in git_status.split('|').filter(|s| !s.is_empty()) {
+        let (count, code) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("invalid git status {:?}", s))?;
+        let count = count
+            .parse::<u64>()
+            .map_err(|_| format!("invalid git status {:?}", s))?;
+        counts.insert(code.trim().to_string(), count);
+    }
+    Ok(counts)
+}
+
+impl Status {
+    fn new(path: &str, branch: &str, git_status: &str) -> Result<Status, String> {
+        let mut status = Status {
+            path: path.to_string(),
+            branch: branch.to_string(),
+            ..Default::default()
+        };
+        status.set_counts(parse_counts(git_status)?);
+        Ok(status)
+    }
+
+    /// Replace the counts of each code, guessing the categories from them.
+    fn set_counts(&mut self, git_status: BTreeMap<String, u64>) {
+        self.categories = Categories::guess(&git_status);
+        self.git_status = git_status;
+    }
+
+    /// Encode the status for storage, in the current layout.
+    fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        migrations::encode(self)
+    }
+
+    /// Decode a stored status, upgrading it from older layouts.
+    fn decode(bytes: &[u8]) -> Result<Status, Box<dyn Error>> {
+        migrations::decode(bytes, migrations::Stored::Status)
+    }
+
+    /// Keep the fields that aren't collected from git from the stored status.
+    fn carry_over(&mut self, existing: Status) {
+        self.tags = existing.tags;
+        self.created_at = existing.created_at;
+        let metadata = std::mem::take(&mut self.metadata);
+        self.metadata = existing.metadata;
+        self.metadata.extend(metadata);
+        self.ttl = self.ttl.or(existing.ttl);
+    }
+
+    /// Whether the status has gone longer than its ttl, or else ttl, without
+    /// an update.
+    fn expired(&self, ttl: Option<u64>, now: u64) -> bool {
+        self.ttl
+            .or(ttl)
+            .is_some_and(|ttl| self.updated_at.saturating_add(ttl) < now)
+    }
+
+    /// Check whether two statuses hold the same data, ignoring timestamps.
+    fn same_as(&self, other: &Status) -> bool {
+        let mut other = other.clone();
+        other.created_at = self.created_at;
+        other.updated_at = self.updated_at;
+        *self == other
+    }
+
+    fn has_tag(&self, tag: &Option<String>) -> bool {
+        tag.as_ref().is_none_or(|t| self.tags.contains(t))
+    }
+
+    fn has_remote(&self, remote: &Option<String>) -> bool {
+        let remote = match remote {
+            Some(remote) => git::normalize_url(remote),
+            None => return true,
+        };
+        self.remote
+            .as_ref()
+            .is_some_and(|r| git::normalize_url(&r.url).contains(&remote))
+    }
+
+    /// The repository the status is a worktree of, or its own path when
+    /// that isn't known.
+    fn repo(&self) -> &str {
+        self.repo_id.as_deref().unwrap_or(&self.path)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.git_status.values().any(|&v| v > 0)
+    }
+
+    /// Arrows for the commits ahead and behind upstream, e.g. `⇡2 ⇣1`, or an
+    /// empty string when the branch is up to date.
+    fn arrows(&self) -> String {
+        let mut arrows = Vec::new();
+        if self.ahead > 0 {
+            arrows.push(format!("⇡{}", self.ahead));
+        }
+        if self.behind > 0 {
+            arrows.push(format!("⇣{}", self.behind));
+        }
+        arrows.join(" ")
+    }
+
+    /// The branch followed by any operation, arrows and stashes, e.g.
+    /// `main|REBASE 2/5 ⇡2 *3`. A detached HEAD is shown in parentheses, e.g.
+    /// `(v1.2.3~2)`. Bare repositories are marked `(bare)`, shallow clones
+    /// `(shallow)` and sparse checkouts `◌`.
+    fn branch_line(&self) -> String {
+        let stashes = if self.stashes > 0 {
+            format!("*{}", self.stashes)
+        } else {
+            String::new()
+        };
+        let mut branch = match self.head_state {
+            HeadState::Detached => format!("({})", self.branch.trim()),
+            _ => self.branch.trim().to_string(),
+        };
+        if let Some(operation) = &self.operation {
+            branch.push_str(&format!("|{}", operation));
+        }
+        let flags = [
+            (self.bare, "(bare)"),
+            (self.shallow, "(shallow)"),
+            (self.sparse, "◌"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, flag)| *flag)
+        .collect::<Vec<_>>()
+        .join(" ");
+        [branch, flags, self.arrows(), stashes]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// When the status was created and updated relative to now, e.g.
+    /// `created 3d ago, updated 5m ago`.
+    fn times(&self) -> String {
+        let mut times = Vec::new();
+        if self.created_at > 0 {
+            times.push(format!("created {}", clock::ago(self.created_at)));
+        }
+        if self.updated_at > 0 {
+            times.push(format!("updated {}", clock::ago(self.updated_at)));
+        }
+        times.join(", ")
+    }
+
+    /// How many changes there are across every code.
+    fn changes(&self) -> u64 {
+        self.git_status.values().sum()
+    }
+
+    /// The status on one line, e.g. for list.
+    fn line(&self, show_times: bool, palette: &color::Palette) -> String {
+        let mut line = format!("{}: {}", self.path, self.branch_line());
+        if let Some(upstream) = &self.upstream {
+            line.push_str(&format!(" -> {}", upstream));
+        }
+        line.push_str(&format!(" {:?}", self.git_status));
+        if !self.tags.is_empty() {
+            line.push_str(&format!(" {:?}", self.tags));
+        }
+        if !self.metadata.is_empty() {
+            line.push_str(&format!(" {:?}", self.metadata));
+        }
+        if show_times && (self.created_at > 0 || self.updated_at > 0) {
+            line.push_str(&format!(" {}", self.times()));
+        }
+        format!(
+            "{}{}",
+            palette.paint(self.changes(), &line),
+            self.alert(palette)
+        )
+    }
+
+    /// How many paths have merge conflicts, from codes like UU and AA.
+    fn conflicts(&self) -> u64 {
+        self.categories.conflicted
+    }
+
+    /// The conflicts painted to stand out, e.g. ` !2`, or nothing when there
+    /// are none.
+    fn alert(&self, palette: &color::Palette) -> String {
+        if self.conflicts() == 0 {
+            return String::new();
+        }
+        format!(" {}", palette.conflicted(&format!("!{}", self.conflicts())))
+    }
+
+    /// The porcelain counts, e.g. `1 ?? | 2 M `.
+    fn counts(&self) -> String {
+        self.git_status
+            .iter()
+            .map(|(k, v)| format!("{} {} ", v, k))
+            .collect::<Vec<_>>()
+            .join("| ")
+    }
+
+    /// The status on a few lines: the branch line, the counts and, with
+    /// show_times, when it was created and updated.
+    fn describe(&self, show_times: bool, palette: &color::Palette) -> String {
+        let mut lines = vec![
+            palette.paint(self.changes(), &self.branch_line()) + &self.alert(palette),
+            palette.paint(self.changes(), &self.counts()),
+        ];
+        if show_times {
+            lines.push(self.times());
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Clone)]
+struct Database {
+    backend: Arc<dyn store::Backend>,
+    statuses: Arc<dyn store::StatusStore>,
+    history: Arc<dyn store::StatusStore>,
+    meta: Arc<dyn store::StatusStore>,
+    quarantine: Arc<dyn store::StatusStore>,
+    /// How long statuses without their own ttl are kept without an update.
+    ttl: Option<u64>,
+    /// How many statuses are kept before the least recently updated are
+    /// dropped.
+    max_entries: Option<usize>,
+    /// The backups taken as statuses are put, if any are.
+    backups: Option<backup::Auto>,
+    /// Who hears about the changes made through this database.
+    events: Arc<events::Events>,
+    read_only: bool,
+}
+
+impl Database {
+    fn new(
+        kind: store::Kind,
+        path: &str,
+        read_only: bool,
+        retry: store::Retry,
+    ) -> Result<Database, Box<dyn Error>> {
+        let db = Database::open(kind, path, read_only, retry)?;
+        if !read_only {
+            db.migrate_keys()?;
+        } else if db.has_bincode_keys()? {
+            // Rewriting the keys takes the lock, but only the once.
+            drop(db);
+            Database::new(kind, path, false, retry)?;
+            return Database::new(kind, path, true, retry);
+        }
+        Ok(db)
+    }
+
+    /// Open the database in path read-only and leave it exactly as it is,
+    /// keys of older versions included, which is enough to list everything
+    /// in it but not to find a path.
+    fn read(kind: store::Kind, path: &str) -> Result<Database, Box<dyn Error>> {
+        Database::open(kind, path, true, store::Retry::NO_WAIT)
+    }
+
+    fn open(
+        kind: store::Kind,
+        path: &str,
+        read_only: bool,
+        retry: store::Retry,
+    ) -> Result<Database, Box<dyn Error>> {
+        let opened = store::open(kind, path, read_only, retry)?;
+        Ok(Database {
+            backend: opened.backend,
+            statuses: opened.statuses,
+            history: opened.history,
+            meta: opened.meta,
+            quarantine: opened.quarantine,
+            ttl: None,
+            max_entries: None,
+            backups: None,
+            events: Arc::default(),
+            read_only,
+        })
+    }
+
+    fn update(self, status: Status) -> Result<(), Box<dyn std::error::Error>> {
+        self.update_all(&[status])
+    }
+
+    /// Store statuses collected from git, keeping the fields of the stored
+    /// statuses that git doesn't know about.
+    fn update_all(&self, statuses: &[Status]) -> Result<(), Box<dyn std::error::Error>> {
+        let now = clock::now();
+        let mut updated = Vec::new();
+        for status in statuses {
+            let mut status = status.clone();
+            match self.find(&status.path)? {
+                Some(existing) => status.carry_over(existing),
+                None => status.created_at = now,
+            }
+            status.updated_at = now;
+            updated.push(status);
+        }
+        let mut transaction = store::Transaction::default();
+        self.stage(&mut transaction, &updated)?;
+        self.record(&mut transaction, &updated)?;
+        self.commit(transaction)?;
+        self.sweep(&updated)?;
+        self.rotate()?;
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    /// Store the statuses that differ from the stored ones, skipping the
+    /// write entirely when none do. Returns how many were stored.
+    fn update_changed(&self, statuses: &[Status]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut changed = Vec::new();
+        for status in statuses {
+            let mut status = status.clone();
+            if let Some(existing) = self.find(&status.path)? {
+                status.carry_over(existing.clone());
+                if status.same_as(&existing) {
+                    continue;
+                }
+            }
+            changed.push(status);
+        }
+        if !changed.is_empty() {
+            self.update_all(&changed)?;
+        }
+        Ok(changed.len())
+    }
+
+    /// Store statuses exactly as given.
+    fn save(&self, statuses: &[Status]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut transaction = store::Transaction::default();
+        self.stage(&mut transaction, statuses)?;
+        self.commit(transaction)
+    }
+
+    /// Apply transaction, which every write to the statuses goes through,
+    /// and tell the subscribers about each status it changed or removed.
+    fn commit(&self, transaction: store::Transaction) -> Result<(), Box<dyn Error>> {
+        let mut changes = BTreeMap::new();
+        if let Some(batch) = transaction.get(store::Space::Statuses) {
+            if !self.events.is_empty() {
+                for (key, value) in batch.ops() {
+                    changes.insert(key.to_vec(), value.map(Status::decode));
+                }
+            }
+        }
+        self.backend.commit(transaction)?;
+        for (key, change) in changes {
+            self.events.send(match change {
+                Some(Ok(status)) => events::Event::Changed {
+                    status: Box::new(status),
+                },
+                Some(Err(_)) => continue,
+                None => events::Event::Removed {
+                    path: String::from_utf8_lossy(&key).into_owned(),
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Write statuses as they are in transaction.
+    fn stage(
+        &self,
+        transaction: &mut store::Transaction,
+        statuses: &[Status],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let batch = transaction.batch(store::Space::Statuses);
+        for status in statuses {
+            batch.insert(status.path.as_bytes(), status.encode()?);
+        }
+        Ok(())
+    }
+
+    /// Insert the statuses, removing any others when replace is set. Returns
+    /// how many statuses were created, updated and removed.
+    fn import(
+        &self,
+        statuses: &[Status],
+        replace: bool,
+    ) -> Result<(usize, usize, usize), Box<dyn std::error::Error>> {
+        let existing = self.list()?.into_iter().map(|s| s.path).collect::<Vec<_>>();
+        let mut transaction = store::Transaction::default();
+        let batch = transaction.batch(store::Space::Statuses);
+        let mut removed = 0;
+        if replace {
+            for path in &existing {
+                if !statuses.iter().any(|s| &s.path == path) {
+                    batch.remove(path.as_bytes());
+                    removed += 1;
+                }
+            }
+        }
+        let mut updated = 0;
+        for status in statuses {
+            if existing.contains(&status.path) {
+                updated += 1;
+            }
+            batch.insert(status.path.as_bytes(), status.encode()?);
+        }
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok((statuses.len() - updated, updated, removed))
+    }
+
+    /// The status of path, unless it has expired, in which case it's
+    /// removed.
+    fn find(&self, path: &str) -> Result<Option<Status>, Box<dyn std::error::Error>> {
+        let status = match self.statuses.get(path.as_bytes())? {
+            Some(v) => Status::decode(&v)?,
+            None => return Ok(None),
+        };
+        if status.expired(self.ttl, clock::now()) {
+            if !self.read_only {
+                self.expire(&[status.path])?;
+            }
+            return Ok(None);
+        }
+        Ok(Some(status))
+    }
+
+    /// Remove every expired status, which read-only opens only hide, and
+    /// the least recently updated ones beyond max_entries other than those
+    /// in kept.
+    fn sweep(&self, kept: &[Status]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut statuses = self.list()?;
+        let max = match self.max_entries {
+            Some(max) if statuses.len() > max => max,
+            _ => return Ok(()),
+        };
+        statuses.retain(|s| !kept.iter().any(|k| k.path == s.path));
+        statuses.sort_by(|x, y| (x.updated_at, &x.path).cmp(&(y.updated_at, &y.path)));
+        let extra = (statuses.len() + kept.len()).saturating_sub(max);
+        let evicted = statuses
+            .into_iter()
+            .take(extra)
+            .map(|s| s.path)
+            .collect::<Vec<_>>();
+        self.expire(&evicted)
+    }
+
+    /// Remove the statuses and history of paths.
+    fn expire(&self, paths: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut transaction = store::Transaction::default();
+        for path in paths {
+            transaction
+                .batch(store::Space::Statuses)
+                .remove(path.as_bytes());
+            self.forget(&mut transaction, path)?;
+        }
+        self.commit(transaction)?;
+        self.backend.flush()
+    }
+
+    /// Whether path has a status that hasn't expired.
+    fn exists(&self, path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.find(path)?.is_some())
+    }
+
+    fn rename(self, from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut status = self
+            .find(from)?
+            .ok_or_else(|| format!("no status found for {}", from))?;
+        if self.exists(to)? {
+            return Err(format!("a status already exists for {}", to).into());
+        }
+        status.path = to.to_string();
+
+        let mut transaction = store::Transaction::default();
+        let batch = transaction.batch(store::Space::Statuses);
+        batch.insert(to.as_bytes(), status.encode()?);
+        batch.remove(from.as_bytes());
+        self.move_history(&mut transaction, from, to)?;
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    fn delete(self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.exists(path)? {
+            return Err(format!("no status found for {}", path).into());
+        }
+        let mut transaction = store::Transaction::default();
+        transaction
+            .batch(store::Space::Statuses)
+            .remove(path.as_bytes());
+        self.forget(&mut transaction, path)?;
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Status>, Box<dyn std::error::Error>> {
+        let now = clock::now();
+        let mut statuses = Vec::new();
+        let mut expired = Vec::new();
+        for (k, v) in self.statuses.iter()? {
+            // One bad value shouldn't hide every other status.
+            let status = match Status::decode(&v) {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!(
+                        "skipping {}: {}; run doctor --fix to set it aside",
+                        String::from_utf8_lossy(&k),
+                        e
+                    );
+                    continue;
+                }
+            };
+            if status.expired(self.ttl, now) {
+                expired.push(status.path);
+            } else {
+                statuses.push(status);
+            }
+        }
+        if !expired.is_empty() && !self.read_only {
+            self.expire(&expired)?;
+        }
+        Ok(statuses)
+    }
+
+    /// Remove statuses whose path no longer exists on disk, returning the
+    /// removed paths. Nothing is removed when dry_run is set.
+    fn prune(self, dry_run: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let missing = self
+            .list()?
+            .into_iter()
+            .filter(|s| !Path::new(&s.path).exists())
+            .map(|s| s.path)
+            .collect::<Vec<_>>();
+        if !dry_run {
+            self.expire(&missing)?;
+        }
+        Ok(missing)
+    }
+
+    fn clear(self) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut transaction = store::Transaction::default();
+        let count = self.clear_statuses(&mut transaction)?;
+        self.clear_history(&mut transaction)?;
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok(count)
+    }
+
+    /// Remove every status in transaction, returning how many there were.
+    fn clear_statuses(
+        &self,
+        transaction: &mut store::Transaction,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let batch = transaction.batch(store::Space::Statuses);
+        let mut count = 0;
+        for (key, _) in self.statuses.iter()? {
+            batch.remove(key);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Trim whitespace and any trailing slash so paths match the keys put stores.
+fn normalize_path(path: &str) -> String {
+    path.trim()
+        .strip_suffix('/')
+        .unwrap_or(path.trim())
+        .to_string()
+}
+
+fn main() {
+    // Exit only once run has returned and dropped the database, which
+    // removes any temporary copy a read-only open made.
+    let code = run().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        EXIT_ERROR
+    });
+    process::exit(code);
+}
+
+/// Run the subcommand, returning the code to exit with.
+fn run() -> Result<i32, Box<dyn std::error::Error>> {
+    // Create our config directory if it doesn't exist
+    let dir = xdg::config_dir()?;
+    fs::create_dir_all(dir.clone())?;
+
+    // Usage errors exit like any other error rather than with clap's 2,
+    // which means a path isn't tracked.
+    let mut cli = Cli::try_parse().unwrap_or_else(|e| {
+        if e.use_stderr() {
+            let _ = e.print();
+            process::exit(EXIT_ERROR);
+        }
+        e.exit()
+    });
+
+    // The daemon socket lives with the database so each database gets its
+    // own daemon.
+    let db_dir = match &cli.db {
+        Some(db) => Path::new(db).to_path_buf(),
+        None => profiles::dir(&xdg::data_dir()?, &cli.profile)?,
+    };
+    if let Commands::Profiles(p) = &cli.command {
+        match p.action {
+            ProfilesAction::List => {
+                for profile in profiles::list(&xdg::data_dir()?)? {
+                    let mark = if profile == cli.profile { '*' } else { ' ' };
+                    println!("{} {}", mark, profile);
+                }
+            }
+        }
+        return Ok(0);
+    }
+    xdg::create_private(&db_dir)?;
+    let socket = cli
+        .socket
+        .clone()
+        .unwrap_or_else(|| db_dir.join("daemon.sock"));
+    let db_dir = db_dir.into_os_string().into_string().unwrap();
+    let dir = dir.into_os_string().into_string().unwrap();
+    match &mut cli.command {
+        Commands::Get(g) => {
+            if let Some(name) = &g.template {
+                g.format = Some(template::load(&dir, name)?);
+            }
+        }
+        Commands::List(l) => {
+            if let Some(name) = &l.template {
+                l.layout = Some(template::load(&dir, name)?);
+            }
+        }
+        _ => {}
+    }
+    if let Commands::Completions(c) = &cli.command {
+        print!("{}", completions::generate(&Cli::command(), c.shell));
+        return Ok(0);
+    }
+    if let Commands::Init(c) = &cli.command {
+        print!("{}", init::hook(c.shell, Cli::command().get_name()));
+        return Ok(0);
+    }
+
+    let config = config::Config::load(&dir)?;
+    let palette = color::Palette::new(cli.color, config.color.clone());
+    let backend = cli.backend.or(config.backend).unwrap_or(store::Kind::Sled);
+    let mut retry = if cli.no_wait {
+        store::Retry::NO_WAIT
+    } else {
+        config.open_retry
+    };
+
+    // A running daemon holds the database lock, so let it answer instead.
+    // With a broker configured, writes start one to go through.
+    let mut client = daemon::Client::connect(&socket);
+    if let (None, Some(broker), Commands::Put(_) | Commands::Delete(_)) =
+        (&client, &config.broker, &cli.command)
+    {
+        if backend != store::Kind::Memory {
+            let idle = clock::parse_duration(&broker.idle)
+                .map_err(|e| format!("invalid broker idle in config: {}", e))?;
+            client = daemon::spawn(&db_dir, backend, idle, &socket)?;
+        }
+    }
+    if let Some(mut client) = client {
+        match &cli.command {
+            Commands::List(l) => return l.print(client.list()?, &palette).map(|_| 0),
+            Commands::Put(p) => {
+                let mut written = false;
+                for status in p.statuses(|path| client.find(path))? {
+                    if p.if_changed {
+                        written |= client.update_changed(status)?;
+                    } else {
+                        client.update(status)?;
+                        written = true;
+                    }
+                }
+                return Ok(if p.if_changed && !written { 1 } else { 0 });
+            }
+            Commands::Get(g) => return g.print(|path| client.find(path), &palette, &config),
+            Commands::Summary(su) => {
+                return su.print(client.list()?, &palette, &config);
+            }
+            Commands::Delete(d) => return client.delete(&normalize_path(&d.path)).map(|_| 0),
+            Commands::Daemon(_) | Commands::Serve(_) => {
+                return Err(format!("a daemon is already listening on {}", socket.display()).into())
+            }
+            // Anything else that writes needs the lock, which a broker gives up.
+            c if !c.reads_only() => {
+                let stepped_aside = client.stop()?;
+                // Even --no-wait waits for the broker it just asked to exit.
+                if stepped_aside && cli.no_wait {
+                    retry = store::Retry::STEPPED_ASIDE;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let read_only = cli.read_only || cli.command.reads_only();
+    let open = |read_only: bool| -> Result<Database, Box<dyn Error>> {
+        let mut db = Database::new(backend, &db_dir, read_only, retry)?;
+        db.ttl = config
+            .ttl
+            .as_deref()
+            .map(clock::parse_duration)
+            .transpose()
+            .map_err(|e| format!("invalid ttl in config: {}", e))?;
+        db.max_entries = config.max_entries;
+        db.backups = config
+            .backups
+            .as_ref()
+            .map(|r| backup::Auto::new(r, Path::new(&db_dir)))
+            .transpose()?;
+        Ok(db)
+    };
+    let db = open(read_only)?;
+    let mut code = 0;
+    match &cli.command {
+        Commands::List(l) => {
+            l.print(db.list()?, &palette)?;
+        }
+        Commands::Put(p) => {
+            let statuses = p.statuses(|path| db.find(path))?;
+            if p.if_changed {
+                if db.update_changed(&statuses)? == 0 {
+                    code = 1;
+                }
+            } else {
+                db.update_all(&statuses)?;
+            }
+        }
+        Commands::Get(g) => {
+            code = g.print(|path| db.find(path), &palette, &config)?;
+        }
+        Commands::Delete(d) => {
+            db.delete(&normalize_path(&d.path))?;
+        }
+        Commands::Clear(c) => {
+            if !c.yes {
+                return Err("refusing to clear the database without --yes".into());
+            }
+            println!("removed {} statuses", db.clear()?);
+        }
+        Commands::Prune(p) => {
+            let removed = db.prune(p.dry_run)?;
+            for path in &removed {
+                println!("{}", path);
+            }
+            if p.dry_run {
+                println!("would remove {} statuses", removed.len());
+            } else {
+                println!("removed {} statuses", removed.len());
+            }
+        }
+        Commands::Rename(r) => {
+            db.rename(&normalize_path(&r.from), &normalize_path(&r.to))?;
+        }
+        Commands::Exists(e) => {
+            if !db.exists(&normalize_path(&e.path))? {
+                code = EXIT_NOT_TRACKED;
+            }
+        }
+        Commands::Count(c) => {
+            let count = db
+                .list()?
+                .iter()
+                .filter(|s| !c.dirty || s.is_dirty())
+                .filter(|s| c.branch.as_ref().is_none_or(|b| &s.branch == b))
+                .filter(|s| s.has_tag(&c.tag))
+                .count();
+            println!("{}", count);
+        }
+        Commands::Search(q) => {
+            let pattern = q
+                .pattern
+                .as_deref()
+                .map(pattern::Pattern::new)
+                .transpose()?;
+            for status in db.list()? {
+                let matched = match (&pattern, &q.contains) {
+                    (Some(p), _) => p.is_match(&status.path),
+                    (None, Some(c)) => status.path.contains(c.as_str()),
+                    (None, None) => false,
+                };
+                if matched {
+                    println!("{}", status.path);
+                    println!("{}", status.describe(false, &palette));
+                }
+            }
+        }
+        Commands::Scan(sc) => {
+            let root = fs::canonicalize(&sc.root)?;
+            let mut statuses = Vec::new();
+            for repository in git::find_repositories(&root, sc.max_depth)? {
+                match git::read(&repository, &git::Options::default()) {
+                    Ok(status) => statuses.push(status),
+                    Err(e) => eprintln!("skipping {}: {}", repository.display(), e),
+                }
+            }
+            println!("scanned {} repositories", statuses.len());
+            db.update_all(&statuses)?;
+        }
+        Commands::Refresh(r) => {
+            let paths = match &r.path {
+                Some(path) => vec![normalize_path(path)],
+                None => db.list()?.into_iter().map(|s| s.path).collect(),
+            };
+            let options = git::Options {
+                submodules: r.submodules,
+                ignored: r.include_ignored,
+            };
+            let mut statuses = Vec::new();
+            for path in paths {
+                match git::read(Path::new(&path), &options) {
+                    Ok(status) => statuses.push(status),
+                    Err(e) if r.all => eprintln!("skipping {}: {}", path, e),
+                    Err(e) => return Err(e),
+                }
+            }
+            db.update_all(&statuses)?;
+        }
+        Commands::Watch(w) => {
+            let repositories = match &w.root {
+                Some(root) => git::find_repositories(&fs::canonicalize(root)?, None)?,
+                None => db.list()?.into_iter().map(|s| s.path.into()).collect(),
+            };
+            // Only hold the database open while writing so other invocations
+            // aren't locked out between checks.
+            drop(db);
+            let mut last: HashMap<PathBuf, Status> = HashMap::new();
+            let mut seen: HashMap<PathBuf, git::Fingerprint> = HashMap::new();
+            let mut ignored: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+            loop {
+                let mut changed = Vec::new();
+                for repository in &repositories {
+                    // Git is only run for repositories that changed. The
+                    // fingerprint is taken before git so that edits made
+                    // while it runs are seen next time; git refreshing the
+                    // index only costs one more run.
+                    let fingerprint = git::fingerprint(
+                        repository,
+                        ignored.get(repository).map_or(&[], Vec::as_slice),
+                    );
+                    if fingerprint.is_some() && fingerprint.as_ref() == seen.get(repository) {
+                        continue;
+                    }
+                    let status = match git::read(repository, &git::Options::default()) {
+                        Ok(status) => status,
+                        Err(_) => continue,
+                    };
+                    if let Some(fingerprint) = fingerprint {
+                        seen.insert(repository.clone(), fingerprint);
+                        ignored.insert(repository.clone(), git::ignored_directories(repository));
+                    }
+                    if last.get(repository) != Some(&status) {
+                        println!("updated {}", status.path);
+                        changed.push(status);
+                    }
+                }
+                if !changed.is_empty() {
+                    open(false)?.update_all(&changed)?;
+                    for status in changed {
+                        last.insert(PathBuf::from(&status.path), status);
+                    }
+                }
+                thread::sleep(time::Duration::from_secs(w.interval));
+            }
+        }
+        Commands::Daemon(d) => {
+            daemon::serve(db, &socket, d.idle)?;
+        }
+        Commands::Serve(s) => match &s.http {
+            Some(addr) => {
+                let (shared, socket) = (db.clone(), socket.clone());
+                thread::spawn(move || {
+                    if let Err(e) = daemon::serve(shared, &socket, None) {
+                        eprintln!("failed to serve {}: {}", socket.display(), e);
+                    }
+                });
+                http::serve(db, addr)?;
+            }
+            None => daemon::serve(db, &socket, None)?,
+        },
+        Commands::Export(e) => {
+            let export = export::Export::new(db.list()?).to_string(e.format)?;
+            match &e.output {
+                Some(output) => fs::write(output, export)?,
+                None => print!("{}", export),
+            }
+        }
+        Commands::Import(i) => {
+            let format = i
+                .format
+                .unwrap_or_else(|| export::Format::from_path(&i.input));
+            let mut statuses =
+                export::Export::from_str(&fs::read_to_string(&i.input)?, format)?.statuses;
+            for status in &mut statuses {
+                status.path = normalize_path(&status.path);
+            }
+            let (created, updated, removed) = db.import(&statuses, i.replace)?;
+            println!(
+                "created {}, updated {}, removed {} statuses",
+                created, updated, removed
+            );
+        }
+        Commands::Stats => {
+            stats::Stats::new(&db.list()?).print();
+            println!(
+                "size on disk: {}",
+                compact::format_size(db.backend.size_on_disk()?)
+            );
+        }
+        Commands::History(h) => {
+            let entries = db.history_of(&normalize_path(&h.path))?;
+            let skip = h.limit.map_or(0, |l| entries.len().saturating_sub(l));
+            for entry in entries.iter().skip(skip) {
+                println!(
+                    "{} {} {}",
+                    clock::format(entry.timestamp),
+                    entry.status.branch_line(),
+                    entry.status.counts()
+                );
+            }
+        }
+        Commands::Diff(d) => {
+            let path = normalize_path(&d.path);
+            let entries = db.history_of(&path)?;
+            let find = |when: &str| -> Result<usize, Box<dyn Error>> {
+                let timestamp = clock::parse(when)?;
+                history::position_at(&entries, timestamp).ok_or_else(|| {
+                    format!("no history for {} at {}", path, clock::format(timestamp)).into()
+                })
+            };
+            let to = match &d.to {
+                Some(when) => find(when)?,
+                None => entries
+                    .len()
+                    .checked_sub(1)
+                    .ok_or_else(|| format!("no history for {}", path))?,
+            };
+            let from = match &d.from {
+                Some(when) => find(when)?,
+                None => to
+                    .checked_sub(1)
+                    .ok_or_else(|| format!("no earlier history for {}", path))?,
+            };
+            let changes = history::changes(&entries[from].status, &entries[to].status);
+            if changes.is_empty() {
+                println!("no changes");
+            }
+            for change in changes {
+                println!("{}", change);
+            }
+        }
+        Commands::Tag(t) | Commands::Untag(t) => {
+            let path = normalize_path(&t.path);
+            let mut status = db
+                .find(&path)?
+                .ok_or_else(|| format!("no status found for {}", path))?;
+            for tag in &t.tag {
+                if matches!(cli.command, Commands::Tag(_)) {
+                    status.tags.insert(tag.clone());
+                } else {
+                    status.tags.remove(tag);
+                }
+            }
+            db.save(&[status])?;
+            db.backend.flush()?;
+        }
+        Commands::Doctor(d) => {
+            let (checked, problems) = db.check()?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            println!(
+                "checked {} statuses, found {} problems",
+                checked,
+                problems.len()
+            );
+            if d.fix || d.rebuild {
+                db.repair(&problems)?;
+                println!("fixed {} problems", problems.len());
+            }
+            if d.rebuild {
+                let (statuses, entries) = db.rebuild()?;
+                println!(
+                    "rebuilt {} statuses and {} history entries",
+                    statuses, entries
+                );
+            }
+            let quarantined = db.quarantine.len()?;
+            if quarantined > 0 {
+                println!("{} values are set aside in quarantine", quarantined);
+            }
+            if !d.fix && !d.rebuild && !problems.is_empty() {
+                return Err("run doctor --fix to repair the problems".into());
+            }
+        }
+        Commands::Compact => {
+            let (before, after) = db.compact(Path::new(&db_dir))?;
+            println!(
+                "compacted from {} to {}",
+                compact::format_size(before),
+                compact::format_size(after)
+            );
+        }
+        Commands::Touch(t) => {
+            let path = normalize_path(&t.path);
+            let mut status = db
+                .find(&path)?
+                .ok_or_else(|| format!("no status found for {}", path))?;
+            status.updated_at = clock::now();
+            db.save(&[status])?;
+            db.backend.flush()?;
+        }
+        Commands::Stale(st) => {
+            let cutoff = clock::now().saturating_sub(st.older_than);
+            for status in db.list()?.iter().filter(|s| s.updated_at < cutoff) {
+                println!("{}", status.line(false, &palette));
+            }
+        }
+        Commands::Summary(su) => {
+            code = su.print(db.list()?, &palette, &config)?;
+        }
+        Commands::Completions(_) => {
+            unreachable!("completions are printed before opening the database")
+        }
+        Commands::Backup(b) => {
+            let backup = db.backup()?;
+            fs::write(&b.output, backup.to_string()?)?;
+            println!(
+                "backed up {} statuses and {} history entries",
+                backup.statuses.len(),
+                backup.history.len()
+            );
+        }
+        Commands::Restore(r) if r.list => {
+            for path in backup::list(&Path::new(&db_dir).join("backups"))? {
+                println!("{}", path.display());
+            }
+        }
+        Commands::Restore(r) => {
+            let input = r.input.as_deref().unwrap_or_default();
+            let backup = backup::Backup::from_str(&fs::read_to_string(input)?)?;
+            let (statuses, history) = (backup.statuses.len(), backup.history.len());
+            db.restore(backup, r.merge)?;
+            println!(
+                "restored {} statuses and {} history entries",
+                statuses, history
+            );
+        }
+        Commands::Merge(m) => {
+            if !store::exists(backend, Path::new(&m.other)) {
+                return Err(format!("no database found in {}", m.other).into());
+            }
+            let (created, updated, kept) = db.merge(&Database::read(backend, &m.other)?)?;
+            println!(
+                "created {}, updated {}, kept {} statuses",
+                created, updated, kept
+            );
+        }
+        Commands::Unset(u) => {
+            let path = normalize_path(&u.path);
+            let mut status = db
+                .find(&path)?
+                .ok_or_else(|| format!("no status found for {}", path))?;
+            for field in &u.field {
+                match field {
+                    Field::Branch => status.branch.clear(),
+                    Field::GitStatus(None) => status.set_counts(BTreeMap::new()),
+                    Field::GitStatus(Some(code)) => {
+                        let mut git_status = status.git_status.clone();
+                        git_status.remove(code);
+                        status.set_counts(git_status);
+                    }
+                    Field::Meta(key) => {
+                        status.metadata.remove(key);
+                    }
+                }
+            }
+            db.save(&[status])?;
+            db.backend.flush()?;
+        }
+        Commands::Migrate(m) => {
+            let (statuses, history) = db.migrate(m.dry_run)?;
+            println!(
+                "{} {} statuses and {} history entries to layout {}",
+                if m.dry_run {
+                    "would migrate"
+                } else {
+                    "migrated"
+                },
+                statuses,
+                history,
+                migrations::CURRENT
+            );
+        }
+        Commands::Init(_) => unreachable!("hooks are printed before opening the database"),
+        Commands::Profiles(_) => unreachable!("profiles are listed before opening the database"),
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The buckets a change with the code xy is in.
+    fn buckets(xy: &str) -> Vec<&'static str> {
+        let mut categories = Categories::default();
+        categories.add(xy, 1);
+        [
+            "staged",
+            "unstaged",
+            "untracked",
+            "ignored",
+            "conflicted",
+            "modified",
+            "added",
+            "deleted",
+            "renamed",
+            "copied",
+        ]
+        .into_iter()
+        .filter(|name| categories.get(name) == Some(1))
+        .collect()
+    }
+
+    #[test]
+    fn every_xy_code_is_sorted() {
+        let table: &[(&str, &[&str])] = &[
+            (" M", &["unstaged", "modified"]),
+            (" T", &["unstaged", "modified"]),
+            (" A", &["unstaged", "added"]),
+            (" D", &["unstaged", "deleted"]),
+            (" R", &["unstaged", "renamed"]),
+            (" C", &["unstaged", "copied"]),
+            ("M ", &["staged", "modified"]),
+            ("MM", &["staged", "unstaged", "modified"]),
+            ("MT", &["staged", "unstaged", "modified"]),
+            ("MD", &["staged", "unstaged", "modified", "deleted"]),
+            ("T ", &["staged", "modified"]),
+            ("TM", &["staged", "unstaged", "modified"]),
+            ("TD", &["staged", "unstaged", "modified", "deleted"]),
+            ("A ", &["staged", "added"]),
+            ("AM", &["staged", "unstaged", "modified", "added"]),
+            ("AT", &["staged", "unstaged", "modified", "added"]),
+            ("AD", &["staged", "unstaged", "added", "deleted"]),
+            ("D ", &["staged", "deleted"]),
+            ("R ", &["staged", "renamed"]),
+            ("RM", &["staged", "unstaged", "modified", "renamed"]),
+            ("RT", &["staged", "unstaged", "modified", "renamed"]),
+            ("RD", &["staged", "unstaged", "deleted", "renamed"]),
+            ("C ", &["staged", "copied"]),
+            ("CM", &["staged", "unstaged", "modified", "copied"]),
+            ("CT", &["staged", "unstaged", "modified", "copied"]),
+            ("CD", &["staged", "unstaged", "deleted", "copied"]),
+            ("DD", &["conflicted"]),
+            ("AU", &["conflicted"]),
+            ("UD", &["conflicted"]),
+            ("UA", &["conflicted"]),
+            ("DU", &["conflicted"]),
+            ("AA", &["conflicted"]),
+            ("UU", &["conflicted"]),
+            ("??", &["untracked"]),
+            ("!!", &["ignored"]),
+            // Porcelain v2 writes an unchanged side as a dot.
+            (".M", &["unstaged", "modified"]),
+            ("A.", &["staged", "added"]),
+        ];
+        for (xy, expected) in table {
+            assert_eq!(&buckets(xy), expected, "{:?}", xy);
+        }
+    }
+
+    #[test]
+    fn single_letters_are_guessed() {
+        let counts = [("M", 2), ("A", 1), ("D", 1), ("U", 3), ("??", 4)]
+            .into_iter()
+            .map(|(code, count)| (code.to_string(), count))
+            .collect();
+        let categories = Categories::guess(&counts);
+        assert_eq!(categories.staged, 1);
+        assert_eq!(categories.unstaged, 3);
+        assert_eq!(categories.modified, 2);
+        assert_eq!(categories.deleted, 1);
+        assert_eq!(categories.conflicted, 3);
+        assert_eq!(categories.untracked, 4);
+    }
+
+    /// What the events received so far say, like `changed /a`.
+    fn received(events: &std::sync::mpsc::Receiver<events::Event>) -> Vec<String> {
+        events
+            .try_iter()
+            .map(|event| match event {
+                events::Event::Changed { status } => format!("changed {}", status.path),
+                events::Event::Removed { path } => format!("removed {}", path),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_write_sends_events() {
+        let db = Database::new(store::Kind::Memory, "", false, store::Retry::NO_WAIT).unwrap();
+        let events = db.events.subscribe();
+        let status = |path: &str| Status {
+            path: path.to_string(),
+            branch: "main".to_string(),
+            updated_at: clock::now(),
+            ..Default::default()
+        };
+
+        db.save(&[status("/a")]).unwrap();
+        assert_eq!(received(&events), ["changed /a"]);
+        db.update_all(&[status("/b")]).unwrap();
+        assert_eq!(received(&events), ["changed /b"]);
+        db.import(&[status("/c")], true).unwrap();
+        assert_eq!(
+            received(&events),
+            ["removed /a", "removed /b", "changed /c"]
+        );
+        db.clone().rename("/c", "/d").unwrap();
+        assert_eq!(received(&events), ["removed /c", "changed /d"]);
+
+        let other = Database::new(store::Kind::Memory, "", false, store::Retry::NO_WAIT).unwrap();
+        other.save(&[status("/e")]).unwrap();
+        db.merge(&other).unwrap();
+        assert_eq!(received(&events), ["changed /e"]);
+        db.restore(
+            backup::Backup {
+                statuses: vec![status("/f")],
+                history: Vec::new(),
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            received(&events),
+            ["removed /d", "removed /e", "changed /f"]
+        );
+        db.clone().delete("/f").unwrap();
+        assert_eq!(received(&events), ["removed /f"]);
+        db.save(&[status("/g")]).unwrap();
+        received(&events);
+        db.clone().clear().unwrap();
+        assert_eq!(received(&events), ["removed /g"]);
     }
-    Ok(())
 }
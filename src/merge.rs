@@ -0,0 +1,34 @@
+//! Combining another database, e.g. one synced from another machine, into
+//! this one.
+
+use std::error::Error;
+
+use crate::store::Transaction;
+use crate::Database;
+
+impl Database {
+    /// Copy the statuses and history of other into this database. When both
+    /// have a status for a path, the one updated most recently wins. Returns
+    /// how many statuses were created, updated and kept.
+    pub fn merge(&self, other: &Database) -> Result<(usize, usize, usize), Box<dyn Error>> {
+        let (mut created, mut updated, mut kept) = (0, 0, 0);
+        let mut newer = Vec::new();
+        for status in other.list()? {
+            match self.find(&status.path)? {
+                None => created += 1,
+                Some(existing) if existing.updated_at < status.updated_at => updated += 1,
+                Some(_) => {
+                    kept += 1;
+                    continue;
+                }
+            }
+            newer.push(status);
+        }
+        let mut transaction = Transaction::default();
+        self.stage(&mut transaction, &newer)?;
+        self.insert_history(&mut transaction, &other.all_history()?)?;
+        self.commit(transaction)?;
+        self.backend.flush()?;
+        Ok((created, updated, kept))
+    }
+}
@@ -0,0 +1,355 @@
+//! The layouts statuses and history entries have been stored in, and the
+//! upgrades between them. Values are upgraded in memory when they're read,
+//! and `migrate` rewrites them in the current layout.
+//!
+//! - 0: bincode of the path, branch and counts.
+//! - 1: the status as JSON, with every later field defaulted.
+//! - 2: JSON in an envelope with its version, e.g.
+//!   `{"version":2,"value":{...}}`, with the categories filled in.
+//! - 3: with the modified, added, copied and ignored categories too.
+//!
+//! Keys were the bincode of the path, a little-endian length and the bytes,
+//! and are now the path itself. They're rewritten once, when a database is
+//! opened.
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::store::{Space, Transaction};
+use crate::{history, Categories, Database, Status};
+
+/// The layout values are written in.
+pub const CURRENT: u64 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u64,
+    value: Value,
+}
+
+/// How statuses were stored in version 0.
+#[derive(Serialize, Deserialize)]
+pub struct LegacyStatus {
+    pub path: String,
+    pub branch: String,
+    pub git_status: std::collections::BTreeMap<String, u64>,
+}
+
+/// How history entries were stored in version 0.
+#[derive(Serialize, Deserialize)]
+pub struct LegacyEntry {
+    pub timestamp: u64,
+    pub status: LegacyStatus,
+}
+
+/// What a value holds, which decides how version 0 is read and where the
+/// status is in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stored {
+    Status,
+    Entry,
+}
+
+/// The version bytes were written in and their value in that layout, with
+/// version 0 already read into JSON.
+fn read(bytes: &[u8], stored: Stored) -> Result<(u64, Value), Box<dyn Error>> {
+    if let Ok(Envelope { version, value }) = serde_json::from_slice(bytes) {
+        if version > CURRENT {
+            return Err(format!(
+                "stored in layout {}, newer than this version's {}; upgrade git-status-tracker",
+                version, CURRENT
+            )
+            .into());
+        }
+        return Ok((version, value));
+    }
+    match serde_json::from_slice(bytes) {
+        Ok(value) => Ok((1, value)),
+        Err(e) => {
+            let value = match stored {
+                Stored::Status => bincode::deserialize::<LegacyStatus>(bytes)
+                    .ok()
+                    .map(serde_json::to_value),
+                Stored::Entry => bincode::deserialize::<LegacyEntry>(bytes)
+                    .ok()
+                    .map(serde_json::to_value),
+            };
+            match value {
+                Some(value) => Ok((0, value?)),
+                None => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Guess the categories of a status stored before they were, from its
+/// counts.
+fn fill_categories(status: Value) -> Result<Value, Box<dyn Error>> {
+    if status.get("categories").is_some() {
+        return Ok(status);
+    }
+    let mut status: Status = serde_json::from_value(status)?;
+    status.categories = Categories::guess(&status.git_status);
+    Ok(serde_json::to_value(status)?)
+}
+
+/// Guess the categories added in version 3 from the counts, keeping the
+/// ones that were stored.
+fn fill_new_categories(status: Value) -> Result<Value, Box<dyn Error>> {
+    let mut status: Status = serde_json::from_value(status)?;
+    let guessed = Categories::guess(&status.git_status);
+    let categories = &mut status.categories;
+    categories.modified = guessed.modified;
+    categories.added = guessed.added;
+    categories.copied = guessed.copied;
+    categories.ignored = guessed.ignored;
+    Ok(serde_json::to_value(status)?)
+}
+
+/// Upgrade a status from the layout of version to the next one.
+fn upgrade_status(version: u64, status: Value) -> Result<Value, Box<dyn Error>> {
+    match version {
+        // Version 0 was read into the JSON of version 1.
+        0 => Ok(status),
+        1 => fill_categories(status),
+        2 => fill_new_categories(status),
+        _ => Err(format!("no upgrade from layout {}", version).into()),
+    }
+}
+
+/// Upgrade value from the layout of version to the current one.
+fn upgrade(mut version: u64, mut value: Value, stored: Stored) -> Result<Value, Box<dyn Error>> {
+    while version < CURRENT {
+        value = match stored {
+            Stored::Status => upgrade_status(version, value)?,
+            Stored::Entry => {
+                let mut entry = value;
+                if let Some(status) = entry.get_mut("status") {
+                    *status = upgrade_status(version, status.take())?;
+                }
+                entry
+            }
+        };
+        version += 1;
+    }
+    Ok(value)
+}
+
+/// Read stored bytes of any version into T.
+pub fn decode<T: for<'de> Deserialize<'de>>(
+    bytes: &[u8],
+    stored: Stored,
+) -> Result<T, Box<dyn Error>> {
+    let (version, value) = read(bytes, stored)?;
+    Ok(serde_json::from_value(upgrade(version, value, stored)?)?)
+}
+
+/// Write value in the current layout.
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(serde_json::to_vec(&Envelope {
+        version: CURRENT,
+        value: serde_json::to_value(value)?,
+    })?)
+}
+
+/// The meta key recording that keys are paths.
+const KEYS: &[u8] = b"keys";
+
+/// The path in a key written by bincode and the rest bytes after it, if the
+/// key is one.
+fn bincode_path(key: &[u8], rest: usize) -> Option<(&str, &[u8])> {
+    let len = u64::from_le_bytes(key.get(..8)?.try_into().ok()?);
+    let end = 8usize.checked_add(usize::try_from(len).ok()?)?;
+    if key.len() != end.checked_add(rest)? {
+        return None;
+    }
+    Some((std::str::from_utf8(&key[8..end]).ok()?, &key[end..]))
+}
+
+impl Database {
+    /// Whether any keys are still the bincode of older versions.
+    pub fn has_bincode_keys(&self) -> Result<bool, Box<dyn Error>> {
+        if self.meta.contains(KEYS)? {
+            return Ok(false);
+        }
+        let status = |(key, _): &(Vec<u8>, Vec<u8>)| bincode_path(key, 0).is_some();
+        let entry = |(key, _): &(Vec<u8>, Vec<u8>)| bincode_path(key, 8).is_some();
+        Ok(self.statuses.iter()?.iter().any(status) || self.history.iter()?.iter().any(entry))
+    }
+
+    /// Rewrite the bincode keys of older versions as paths, unless that's
+    /// been done.
+    pub fn migrate_keys(&self) -> Result<(), Box<dyn Error>> {
+        if self.meta.contains(KEYS)? {
+            return Ok(());
+        }
+        let mut transaction = Transaction::default();
+        let batch = transaction.batch(Space::Statuses);
+        for (key, value) in self.statuses.iter()? {
+            if let Some((path, _)) = bincode_path(&key, 0) {
+                batch.insert(path.as_bytes(), value);
+                batch.remove(key);
+            }
+        }
+        let batch = transaction.batch(Space::History);
+        for (key, value) in self.history.iter()? {
+            if let Some((path, time)) = bincode_path(&key, 8) {
+                let mut new_key = history::prefix(path);
+                new_key.extend_from_slice(time);
+                batch.insert(new_key, value);
+                batch.remove(key);
+            }
+        }
+        transaction
+            .batch(Space::Meta)
+            .insert(KEYS, serde_json::to_vec("path")?);
+        self.commit(transaction)?;
+        self.backend.flush()
+    }
+
+    /// Rewrite every status and history entry stored in an older layout in
+    /// the current one, returning how many of each there were. Nothing is
+    /// written when dry_run is set.
+    pub fn migrate(&self, dry_run: bool) -> Result<(usize, usize), Box<dyn Error>> {
+        let mut counts = Vec::new();
+        let mut transaction = Transaction::default();
+        for (store, stored, space) in [
+            (&self.statuses, Stored::Status, Space::Statuses),
+            (&self.history, Stored::Entry, Space::History),
+        ] {
+            let batch = transaction.batch(space);
+            let mut count = 0;
+            for (key, value) in store.iter()? {
+                let (version, old) = read(&value, stored)?;
+                if version == CURRENT {
+                    continue;
+                }
+                batch.insert(key, encode(&upgrade(version, old, stored)?)?);
+                count += 1;
+            }
+            counts.push(count);
+        }
+        if !dry_run {
+            self.commit(transaction)?;
+            self.backend.flush()?;
+        }
+        Ok((counts[0], counts[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Entry;
+
+    fn legacy() -> LegacyStatus {
+        LegacyStatus {
+            path: "/repo".to_string(),
+            branch: "main".to_string(),
+            git_status: [("M".to_string(), 2), ("UU".to_string(), 1)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn version_0_to_current() {
+        let bytes = bincode::serialize(&legacy()).unwrap();
+        assert_eq!(read(&bytes, Stored::Status).unwrap().0, 0);
+        let status: Status = decode(&bytes, Stored::Status).unwrap();
+        assert_eq!(status.path, "/repo");
+        assert_eq!(status.branch, "main");
+        assert_eq!(status.categories.unstaged, 2);
+        assert_eq!(status.categories.conflicted, 1);
+        assert_eq!(status.conflicts(), 1);
+    }
+
+    #[test]
+    fn version_1_to_current() {
+        let bytes = br#"{"path":"/repo","branch":"main","git_status":{"M":2}}"#;
+        assert_eq!(read(bytes, Stored::Status).unwrap().0, 1);
+        let status: Status = decode(bytes, Stored::Status).unwrap();
+        assert_eq!(status.categories.unstaged, 2);
+    }
+
+    #[test]
+    fn version_1_keeps_categories() {
+        let bytes = br#"{"path":"/repo","branch":"main","git_status":{"M":2},
+            "categories":{"staged":2,"unstaged":0,"untracked":0,"conflicted":0,"renamed":0,"deleted":0}}"#;
+        let status: Status = decode(bytes, Stored::Status).unwrap();
+        assert_eq!(status.categories.staged, 2);
+        assert_eq!(status.categories.unstaged, 0);
+    }
+
+    #[test]
+    fn version_2_to_current() {
+        let bytes = br#"{"version":2,"value":{"path":"/repo","branch":"main","git_status":{"AM":1,"M":2},
+            "categories":{"staged":1,"unstaged":3,"untracked":0,"conflicted":0,"renamed":0,"deleted":0}}}"#;
+        assert_eq!(read(bytes, Stored::Status).unwrap().0, 2);
+        let status: Status = decode(bytes, Stored::Status).unwrap();
+        assert_eq!(status.categories.staged, 1);
+        assert_eq!(status.categories.unstaged, 3);
+        assert_eq!(status.categories.modified, 3);
+        assert_eq!(status.categories.added, 1);
+    }
+
+    #[test]
+    fn current_round_trips() {
+        let status = Status {
+            path: "/repo".to_string(),
+            branch: "main".to_string(),
+            ..Default::default()
+        };
+        let bytes = encode(&status).unwrap();
+        assert_eq!(read(&bytes, Stored::Status).unwrap().0, CURRENT);
+        assert_eq!(decode::<Status>(&bytes, Stored::Status).unwrap(), status);
+    }
+
+    #[test]
+    fn newer_is_refused() {
+        let bytes = br#"{"version":99,"value":{}}"#;
+        let e = decode::<Status>(bytes, Stored::Status).unwrap_err();
+        assert!(e.to_string().contains("layout 99"), "{}", e);
+    }
+
+    #[test]
+    fn entries_upgrade_their_status() {
+        let bytes = bincode::serialize(&LegacyEntry {
+            timestamp: 7,
+            status: legacy(),
+        })
+        .unwrap();
+        assert_eq!(read(&bytes, Stored::Entry).unwrap().0, 0);
+        let entry: Entry = decode(&bytes, Stored::Entry).unwrap();
+        assert_eq!(entry.timestamp, 7);
+        assert_eq!(entry.status.categories.conflicted, 1);
+
+        let bytes =
+            br#"{"timestamp":7,"status":{"path":"/repo","branch":"main","git_status":{"M":1}}}"#;
+        assert_eq!(read(bytes, Stored::Entry).unwrap().0, 1);
+        let entry: Entry = decode(bytes, Stored::Entry).unwrap();
+        assert_eq!(entry.status.categories.unstaged, 1);
+    }
+
+    #[test]
+    fn bincode_keys() {
+        let key = bincode::serialize("/repo").unwrap();
+        assert_eq!(bincode_path(&key, 0), Some(("/repo", &[][..])));
+        let mut history = key.clone();
+        history.extend_from_slice(&7u64.to_be_bytes());
+        assert_eq!(
+            bincode_path(&history, 8),
+            Some(("/repo", &7u64.to_be_bytes()[..]))
+        );
+        assert_eq!(bincode_path(&history, 0), None);
+        assert_eq!(bincode_path(b"/repo", 0), None);
+        assert_eq!(bincode_path(b"/a/long/path/to/a/repo", 0), None);
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        assert!(decode::<Status>(b"\xff", Stored::Status).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! A small regular expression matcher for searching paths.
+//!
+//! Supports literals, `.`, character classes (`[a-z]`, `[^/]`), the escapes
+//! `\d`, `\w` and `\s`, the repetitions `*`, `+` and `?`, and the anchors `^`
+//! and `$`. Groups and alternation are not supported.
+
+use std::error::Error;
+
+#[derive(Debug)]
+enum Atom {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Char(a) => *a == c,
+            Atom::Any => true,
+            Atom::Class { ranges, negated } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Repeat {
+    One,
+    ZeroOrMore,
+    OneOrMore,
+    ZeroOrOne,
+}
+
+#[derive(Debug)]
+struct Node {
+    atom: Atom,
+    repeat: Repeat,
+}
+
+#[derive(Debug)]
+pub struct Pattern {
+    nodes: Vec<Node>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, Box<dyn Error>> {
+        let mut chars = pattern.chars().peekable();
+        let anchored_start = chars.next_if_eq(&'^').is_some();
+        let mut anchored_end = false;
+        let mut nodes: Vec<Node> = Vec::new();
+
+        while let Some(c) = chars.next() {
+            let atom = match c {
+                '$' if chars.peek().is_none() => {
+                    anchored_end = true;
+                    break;
+                }
+                '.' => Atom::Any,
+                '\\' => escape(chars.next().ok_or("pattern ends with a backslash")?),
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let lo = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => chars.next().ok_or("unterminated character class")?,
+                            Some(c) => c,
+                            None => return Err("unterminated character class".into()),
+                        };
+                        if chars.peek() == Some(&'-') {
+                            chars.next();
+                            match chars.next() {
+                                Some(']') => {
+                                    ranges.push((lo, lo));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                Some(hi) => ranges.push((lo, hi)),
+                                None => return Err("unterminated character class".into()),
+                            }
+                        } else {
+                            ranges.push((lo, lo));
+                        }
+                    }
+                    Atom::Class { ranges, negated }
+                }
+                '*' | '+' | '?' => {
+                    return Err(format!("nothing to repeat before '{}'", c).into());
+                }
+                '(' | ')' | '|' | '{' | '}' => {
+                    return Err(format!("unsupported pattern syntax '{}'", c).into());
+                }
+                c => Atom::Char(c),
+            };
+            let repeat = match chars.next_if(|c| matches!(c, '*' | '+' | '?')) {
+                Some('*') => Repeat::ZeroOrMore,
+                Some('+') => Repeat::OneOrMore,
+                Some('?') => Repeat::ZeroOrOne,
+                _ => Repeat::One,
+            };
+            nodes.push(Node { atom, repeat });
+        }
+
+        Ok(Pattern {
+            nodes,
+            anchored_start,
+            anchored_end,
+        })
+    }
+
+    /// Check whether the pattern matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        if self.anchored_start {
+            return self.match_here(&self.nodes, &text);
+        }
+        (0..=text.len()).any(|i| self.match_here(&self.nodes, &text[i..]))
+    }
+
+    fn match_here(&self, nodes: &[Node], text: &[char]) -> bool {
+        let node = match nodes.first() {
+            Some(node) => node,
+            None => return !self.anchored_end || text.is_empty(),
+        };
+        let (min, max) = match node.repeat {
+            Repeat::One => (1, 1),
+            Repeat::ZeroOrOne => (0, 1),
+            Repeat::ZeroOrMore => (0, usize::MAX),
+            Repeat::OneOrMore => (1, usize::MAX),
+        };
+        let available = text.iter().take_while(|&&c| node.atom.matches(c)).count();
+        let most = available.min(max);
+        if most < min {
+            return false;
+        }
+        (min..=most)
+            .rev()
+            .any(|n| self.match_here(&nodes[1..], &text[n..]))
+    }
+}
+
+fn escape(c: char) -> Atom {
+    let ranges = match c {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')],
+        c => return Atom::Char(c),
+    };
+    Atom::Class {
+        ranges,
+        negated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_match_anywhere() {
+        assert!(matches("repo", "/home/me/repo"));
+        assert!(matches("me/re", "/home/me/repo"));
+        assert!(!matches("mine", "/home/me/repo"));
+        assert!(matches("", "/anything"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^/home", "/home/me"));
+        assert!(!matches("^me", "/home/me"));
+        assert!(matches("me$", "/home/me"));
+        assert!(!matches("home$", "/home/me"));
+        assert!(matches("^/home/me$", "/home/me"));
+        assert!(!matches("^/home$", "/home/me"));
+        // A dollar before the end is a literal.
+        assert!(matches("a$b", "a$b"));
+    }
+
+    #[test]
+    fn dots_and_repetition() {
+        assert!(matches("^/src/.*/api$", "/src/work/api"));
+        assert!(matches("^/src/.*/api$", "/src/a/b/api"));
+        assert!(!matches("^/src/.*/api$", "/src/api"));
+        assert!(matches("^ab+c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab?c$", "ac"));
+        assert!(matches("^ab?c$", "abc"));
+        assert!(!matches("^ab?c$", "abbc"));
+        assert!(matches("^a.c$", "a/c"));
+        assert!(!matches("^a.c$", "ac"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("^/repo[0-9]$", "/repo7"));
+        assert!(!matches("^/repo[0-9]$", "/repox"));
+        assert!(matches("^/[^/]+$", "/top"));
+        assert!(!matches("^/[^/]+$", "/top/nested"));
+        assert!(matches("^[a-]+$", "a-a"));
+        assert!(matches("^[]]$", "]"));
+        assert!(matches("^[\\]x]+$", "]x]"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("^v\\d+$", "v12"));
+        assert!(!matches("^v\\d+$", "vx"));
+        assert!(matches("^\\w+$", "snake_case9"));
+        assert!(!matches("^\\w+$", "kebab-case"));
+        assert!(matches("a\\sb", "a b"));
+        assert!(matches("^\\.git$", ".git"));
+        assert!(!matches("^\\.git$", "xgit"));
+        assert!(matches("a\\*", "a*"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |pattern| Pattern::new(pattern).unwrap_err().to_string();
+        assert_eq!(error("*a"), "nothing to repeat before '*'");
+        assert_eq!(error("^+"), "nothing to repeat before '+'");
+        assert_eq!(error("a**"), "nothing to repeat before '*'");
+        assert_eq!(error("a\\"), "pattern ends with a backslash");
+        assert_eq!(error("[a-"), "unterminated character class");
+        assert_eq!(error("[abc"), "unterminated character class");
+        assert_eq!(error("(a|b)"), "unsupported pattern syntax '('");
+        assert_eq!(error("a{2}"), "unsupported pattern syntax '{'");
+    }
+}
@@ -0,0 +1,141 @@
+//! A stable, versioned line format for scripts, unlike the human output
+//! which may change. Version 1 lays out each status as the tab-separated
+//! fields VERSION, PATH, BRANCH, UPSTREAM, AHEAD, BEHIND, STASHES, UPDATED
+//! and COUNTS, e.g.
+//!
+//! ```text
+//! 1\t/src/app\tmain\torigin/main\t2\t0\t1\t1700000000\tM=2 ??=1
+//! ```
+//!
+//! with `\t` standing for a tab. UPDATED is the unix time in seconds, 0
+//! when unknown, and COUNTS is `CODE=N` pairs separated by spaces.
+//! Backslashes, tabs and newlines in fields are escaped as `\\`, `\t` and
+//! `\n`. In codes, `%`, `=` and whitespace are percent-escaped instead, e.g.
+//! `%20M` for ` M`, so the pairs always split cleanly. Fields are only ever added at the end, and anything else bumps
+//! the version.
+
+use crate::Status;
+
+/// The versions this tool can write.
+pub const VERSIONS: &[u32] = &[1];
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Percent-escape the characters that would split a `CODE=N` pair.
+fn escape_code(code: &str) -> String {
+    let mut escaped = String::new();
+    for c in code.chars() {
+        if c == '%' || c == '=' || c.is_whitespace() || c.is_control() {
+            let mut bytes = [0; 4];
+            for b in c.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Parse the version given to --porcelain.
+pub fn version(s: &str) -> Result<u32, String> {
+    match s.trim_start_matches('v').parse::<u32>() {
+        Ok(v) if VERSIONS.contains(&v) => Ok(v),
+        _ => Err(format!("unsupported porcelain version {}", s)),
+    }
+}
+
+/// The line for status in the format version.
+pub fn line(status: &Status, version: u32) -> String {
+    let counts = status
+        .git_status
+        .iter()
+        .map(|(code, count)| format!("{}={}", escape_code(code), count))
+        .collect::<Vec<_>>()
+        .join(" ");
+    [
+        version.to_string(),
+        escape(&status.path),
+        escape(&status.branch),
+        escape(status.upstream.as_deref().unwrap_or_default()),
+        status.ahead.to_string(),
+        status.behind.to_string(),
+        status.stashes.to_string(),
+        status.updated_at.to_string(),
+        counts,
+    ]
+    .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_1() {
+        let status = Status {
+            path: "/src/app".to_string(),
+            branch: "main".to_string(),
+            upstream: Some("origin/main".to_string()),
+            ahead: 2,
+            stashes: 1,
+            updated_at: 1700000000,
+            git_status: [("M".to_string(), 2), ("??".to_string(), 1)].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            line(&status, 1),
+            "1\t/src/app\tmain\torigin/main\t2\t0\t1\t1700000000\t??=1 M=2"
+        );
+        let bare = Status {
+            path: "/new".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(line(&bare, 1), "1\t/new\t\t\t0\t0\t0\t0\t");
+    }
+
+    #[test]
+    fn fields_are_escaped() {
+        let status = Status {
+            path: "/a\tb\\c".to_string(),
+            branch: "x\ny".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(line(&status, 1), "1\t/a\\tb\\\\c\tx\\ny\t\t0\t0\t0\t0\t");
+    }
+
+    #[test]
+    fn codes_are_percent_escaped() {
+        let status = Status {
+            git_status: [
+                (" M".to_string(), 1),
+                ("a=b".to_string(), 2),
+                ("50%".to_string(), 3),
+                ("x\ty".to_string(), 4),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let line = line(&status, 1);
+        let counts = line.rsplit('\t').next().unwrap();
+        assert_eq!(counts, "%20M=1 50%25=3 a%3Db=2 x%09y=4");
+        for pair in counts.split(' ') {
+            assert_eq!(pair.matches('=').count(), 1, "{}", pair);
+        }
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(version("1"), Ok(1));
+        assert_eq!(version("v1"), Ok(1));
+        assert_eq!(
+            version("2"),
+            Err("unsupported porcelain version 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Named databases kept apart under the data directory, e.g. one for work
+//! repositories and one for personal ones. The default profile is the data
+//! directory itself, so databases from before profiles are the default.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT: &str = "default";
+
+/// The directory of the profile's database.
+pub fn dir(data_dir: &Path, profile: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if profile == DEFAULT {
+        return Ok(data_dir.to_path_buf());
+    }
+    let valid = !profile.is_empty()
+        && !profile.starts_with('.')
+        && profile
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!(
+            "invalid profile {:?}: use letters, digits, '-', '_' and '.'",
+            profile
+        )
+        .into());
+    }
+    Ok(data_dir.join("profiles").join(profile))
+}
+
+/// The default profile and every profile that has been used, sorted.
+pub fn list(data_dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut profiles = Vec::new();
+    match fs::read_dir(data_dir.join("profiles")) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    profiles.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    profiles.sort();
+    profiles.insert(0, DEFAULT.to_string());
+    Ok(profiles)
+}
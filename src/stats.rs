@@ -0,0 +1,87 @@
+//! Aggregate metrics across every status in the database.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::Status;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub repositories: u64,
+    pub dirty: u64,
+    pub changes: u64,
+    pub modified: u64,
+    pub untracked: u64,
+    pub branches: BTreeMap<String, u64>,
+    pub codes: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    pub fn new(statuses: &[Status]) -> Stats {
+        let mut stats = Stats::default();
+        for status in statuses {
+            stats.repositories += 1;
+            if status.is_dirty() {
+                stats.dirty += 1;
+            }
+            *stats.branches.entry(status.branch.clone()).or_insert(0) += 1;
+            stats.modified += status.categories.modified;
+            stats.untracked += status.categories.untracked;
+            for (code, count) in &status.git_status {
+                stats.changes += count;
+                *stats.codes.entry(code.clone()).or_insert(0) += count;
+            }
+        }
+        stats
+    }
+
+    pub fn print(&self) {
+        println!("repositories: {}", self.repositories);
+        println!("dirty: {}", self.dirty);
+        println!("changes: {}", self.changes);
+        println!("modified: {}", self.modified);
+        println!("untracked: {}", self.untracked);
+        println!("branches:");
+        for (branch, count) in &self.branches {
+            println!("  {}: {}", branch, count);
+        }
+        println!("codes:");
+        for (code, count) in &self.codes {
+            println!("  {}: {}", code, count);
+        }
+    }
+}
+
+/// How summary lays out its line.
+pub struct SummaryStyle<'a> {
+    pub icon: &'a str,
+    pub separator: &'a str,
+    pub compact: bool,
+}
+
+impl Stats {
+    /// A single line for status bars, e.g. `🌿 12 repos · 3 dirty · 47 changes`.
+    pub fn summary(&self, style: &SummaryStyle) -> String {
+        let parts = [
+            (self.repositories, "repos"),
+            (self.dirty, "dirty"),
+            (self.changes, "changes"),
+        ]
+        .iter()
+        .map(|(n, label)| {
+            if style.compact {
+                n.to_string()
+            } else {
+                format!("{} {}", n, label)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(style.separator);
+        if style.icon.is_empty() {
+            parts
+        } else {
+            format!("{} {}", style.icon, parts)
+        }
+    }
+}
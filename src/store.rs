@@ -0,0 +1,795 @@
+//! Storage backends. A backend holds keyspaces of byte keys and values kept
+//! in key order, like sled's trees: one with the statuses and one with their
+//! history. Everything above works through the StatusStore trait, so a
+//! backend only has to provide that, and commits writes that span several
+//! keyspaces as one Transaction, so they happen together or not at all.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+use clap::ArgEnum;
+use serde::Deserialize;
+use sled::Transactional;
+
+/// Keys and values, in key order.
+pub type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The backends a database can be kept in.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// A sled database directory.
+    Sled,
+    /// One JSON file, db.json, for a handful of repositories.
+    Json,
+    /// Nothing on disk, so everything is gone when the process exits. Only
+    /// useful for a daemon or for trying commands out.
+    Memory,
+}
+
+/// How opening waits while another invocation holds the lock.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retry {
+    /// How many times to try, at least once.
+    pub attempts: u32,
+    /// How long to wait between tries, in milliseconds.
+    pub backoff_ms: u64,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            attempts: 10,
+            backoff_ms: 100,
+        }
+    }
+}
+
+impl Retry {
+    /// Try once and fail straight away.
+    pub const NO_WAIT: Retry = Retry {
+        attempts: 1,
+        backoff_ms: 0,
+    };
+
+    /// Wait for a broker that has stepped aside, which releases the lock
+    /// as its process is torn down, a moment after its connections close.
+    pub const STEPPED_ASIDE: Retry = Retry {
+        attempts: 50,
+        backoff_ms: 10,
+    };
+
+    /// Call open until it succeeds or runs out of attempts.
+    fn run<T>(&self, open: impl Fn() -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+        let mut attempts = 0;
+        loop {
+            match open() {
+                Ok(opened) => return Ok(opened),
+                Err(e) => {
+                    attempts += 1;
+                    if attempts >= self.attempts.max(1) {
+                        if attempts == 1 {
+                            return Err(e);
+                        }
+                        return Err(format!("failed after {} attempts: {}", attempts, e).into());
+                    }
+                }
+            }
+            thread::sleep(time::Duration::from_millis(self.backoff_ms))
+        }
+    }
+}
+
+/// Writes applied together, in order.
+#[derive(Debug, Default)]
+pub struct Batch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.ops.push((key.into(), Some(value.into())));
+    }
+
+    pub fn remove(&mut self, key: impl Into<Vec<u8>>) {
+        self.ops.push((key.into(), None));
+    }
+
+    /// The writes in order, with None for a removal.
+    pub fn ops(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.ops
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_deref()))
+    }
+}
+
+/// The keyspaces of a backend, for writes that span them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Space {
+    Statuses,
+    History,
+    Meta,
+    Quarantine,
+}
+
+impl Space {
+    const ALL: [Space; 4] = [
+        Space::Statuses,
+        Space::History,
+        Space::Meta,
+        Space::Quarantine,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Space::Statuses => "statuses",
+            Space::History => "history",
+            Space::Meta => "meta",
+            Space::Quarantine => "quarantine",
+        }
+    }
+}
+
+/// Batches for several keyspaces, committed together: either every write
+/// in them happens or none does.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    batches: BTreeMap<Space, Batch>,
+}
+
+impl Transaction {
+    /// The writes to space.
+    pub fn batch(&mut self, space: Space) -> &mut Batch {
+        self.batches.entry(space).or_default()
+    }
+
+    /// The writes to space, if there are any.
+    pub fn get(&self, space: Space) -> Option<&Batch> {
+        self.batches.get(&space)
+    }
+}
+
+/// One keyspace of a backend.
+pub trait StatusStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    /// Every entry, in key order.
+    fn iter(&self) -> Result<Entries, Box<dyn Error>>;
+
+    /// Apply every write in batch at once.
+    fn batch(&self, batch: Batch) -> Result<(), Box<dyn Error>>;
+
+    fn put(&self, key: &[u8], value: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let mut batch = Batch::default();
+        batch.insert(key, value);
+        self.batch(batch)
+    }
+
+    fn contains(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// The entries whose keys start with prefix, in key order.
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Entries, Box<dyn Error>> {
+        Ok(self
+            .iter()?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect())
+    }
+
+    fn len(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.iter()?.len())
+    }
+}
+
+/// A keyspace that refuses writes.
+struct ReadOnly(Arc<dyn StatusStore>);
+
+const READ_ONLY: &str = "the database is open read-only";
+
+impl StatusStore for ReadOnly {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.0.get(key)
+    }
+
+    fn iter(&self) -> Result<Entries, Box<dyn Error>> {
+        self.0.iter()
+    }
+
+    fn batch(&self, _: Batch) -> Result<(), Box<dyn Error>> {
+        Err(READ_ONLY.into())
+    }
+
+    fn contains(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.0.contains(key)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Entries, Box<dyn Error>> {
+        self.0.scan_prefix(prefix)
+    }
+
+    fn len(&self) -> Result<usize, Box<dyn Error>> {
+        self.0.len()
+    }
+}
+
+/// A backend that refuses writes.
+struct ReadOnlyBackend(Arc<dyn Backend>);
+
+impl Backend for ReadOnlyBackend {
+    fn commit(&self, _: Transaction) -> Result<(), Box<dyn Error>> {
+        Err(READ_ONLY.into())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.0.flush()
+    }
+
+    fn size_on_disk(&self) -> Result<u64, Box<dyn Error>> {
+        self.0.size_on_disk()
+    }
+
+    fn sled(&self) -> Option<&sled::Db> {
+        self.0.sled()
+    }
+}
+
+/// What a backend does beyond its keyspaces.
+pub trait Backend: Send + Sync {
+    /// Apply every batch in transaction at once.
+    fn commit(&self, transaction: Transaction) -> Result<(), Box<dyn Error>>;
+
+    /// Make every write durable.
+    fn flush(&self) -> Result<(), Box<dyn Error>>;
+
+    fn size_on_disk(&self) -> Result<u64, Box<dyn Error>>;
+
+    /// The sled database behind the backend, for what only sled can do.
+    fn sled(&self) -> Option<&sled::Db> {
+        None
+    }
+}
+
+/// A backend and its keyspaces.
+pub struct Opened {
+    pub backend: Arc<dyn Backend>,
+    pub statuses: Arc<dyn StatusStore>,
+    pub history: Arc<dyn StatusStore>,
+    /// Facts about the database itself, like how its keys are written.
+    pub meta: Arc<dyn StatusStore>,
+    /// Values that couldn't be read, set aside by doctor.
+    pub quarantine: Arc<dyn StatusStore>,
+}
+
+/// Open the backend of kind in dir, retrying while it's locked. A read-only
+/// backend refuses writes and never takes the lock, so it can't get in the
+/// way of writers.
+pub fn open(
+    kind: Kind,
+    dir: &str,
+    read_only: bool,
+    retry: Retry,
+) -> Result<Opened, Box<dyn Error>> {
+    let opened = open_backend(kind, dir, read_only, retry)?;
+    if !read_only {
+        return Ok(opened);
+    }
+    Ok(Opened {
+        backend: Arc::new(ReadOnlyBackend(opened.backend)),
+        statuses: Arc::new(ReadOnly(opened.statuses)),
+        history: Arc::new(ReadOnly(opened.history)),
+        meta: Arc::new(ReadOnly(opened.meta)),
+        quarantine: Arc::new(ReadOnly(opened.quarantine)),
+    })
+}
+
+fn open_backend(
+    kind: Kind,
+    dir: &str,
+    read_only: bool,
+    retry: Retry,
+) -> Result<Opened, Box<dyn Error>> {
+    match kind {
+        Kind::Sled => {
+            // sled can only be opened with its lock, so read-only opens work
+            // on a copy, which sled recovers like it would after a crash.
+            let (copy, db) = if read_only {
+                let (copy, db) = retry.run(|| copy_sled(Path::new(dir)))?;
+                (Some(copy), db)
+            } else {
+                (None, retry.run(|| Ok(sled::open(dir)?))?)
+            };
+            Ok(Opened {
+                statuses: Arc::new(SledStore((*db).clone())),
+                history: Arc::new(SledStore(db.open_tree("history")?)),
+                meta: Arc::new(SledStore(db.open_tree("meta")?)),
+                quarantine: Arc::new(SledStore(db.open_tree("quarantine")?)),
+                backend: Arc::new(SledBackend { db, copy }),
+            })
+        }
+        Kind::Json => {
+            let file = Arc::new(retry.run(|| JsonFile::open(Path::new(dir), !read_only))?);
+            Ok(Opened {
+                statuses: Arc::new(JsonStore {
+                    file: file.clone(),
+                    tree: Space::Statuses.name(),
+                }),
+                history: Arc::new(JsonStore {
+                    file: file.clone(),
+                    tree: Space::History.name(),
+                }),
+                meta: Arc::new(JsonStore {
+                    file: file.clone(),
+                    tree: Space::Meta.name(),
+                }),
+                quarantine: Arc::new(JsonStore {
+                    file: file.clone(),
+                    tree: Space::Quarantine.name(),
+                }),
+                backend: file,
+            })
+        }
+        Kind::Memory => {
+            let memory = Memory::default();
+            Ok(Opened {
+                statuses: memory.0[&Space::Statuses].clone(),
+                history: memory.0[&Space::History].clone(),
+                meta: memory.0[&Space::Meta].clone(),
+                quarantine: memory.0[&Space::Quarantine].clone(),
+                backend: Arc::new(memory),
+            })
+        }
+    }
+}
+
+/// Whether dir has a database of kind in it.
+pub fn exists(kind: Kind, dir: &Path) -> bool {
+    match kind {
+        Kind::Sled => dir.join("conf").exists(),
+        Kind::Json => dir.join(JSON_FILE).exists(),
+        Kind::Memory => false,
+    }
+}
+
+/// Files relative to a database's directory, with their sizes and
+/// modification times.
+type Files = Vec<(PathBuf, u64, time::SystemTime)>;
+
+/// The files of the sled database in dir.
+fn sled_files(dir: &Path) -> Result<Files, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e.into()),
+    };
+    let mut add = |path: PathBuf, metadata: fs::Metadata| -> Result<(), Box<dyn Error>> {
+        files.push((path, metadata.len(), metadata.modified()?));
+        Ok(())
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "blobs" {
+            for blob in fs::read_dir(entry.path())? {
+                let blob = blob?;
+                add(Path::new("blobs").join(blob.file_name()), blob.metadata()?)?;
+            }
+        } else if crate::compact::is_sled_file(&name.to_string_lossy()) {
+            add(PathBuf::from(&name), entry.metadata()?)?;
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copy the files of the sled database in dir to a new temporary directory
+/// and open it. The copy is thrown away if any file changed while it was
+/// made, or if sled can't open it, so it can be tried again.
+fn copy_sled(dir: &Path) -> Result<(PathBuf, sled::Db), Box<dyn Error>> {
+    let copy = std::env::temp_dir().join(format!(
+        "git-status-tracker-{}-{}",
+        std::process::id(),
+        time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)?
+            .as_nanos()
+    ));
+    crate::xdg::create_private(&copy)?;
+    let copied = (|| {
+        fs::create_dir(copy.join("blobs"))?;
+        let files = sled_files(dir)?;
+        for (file, _, _) in &files {
+            fs::copy(dir.join(file), copy.join(file))?;
+        }
+        if sled_files(dir)? != files {
+            return Err("the database changed while it was copied".into());
+        }
+        Ok(sled::open(&copy)?)
+    })();
+    match copied {
+        Ok(db) => Ok((copy, db)),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&copy);
+            Err(e)
+        }
+    }
+}
+
+struct SledBackend {
+    db: sled::Db,
+    /// The temporary copy the database was opened from, if it's read-only.
+    copy: Option<PathBuf>,
+}
+
+impl Drop for SledBackend {
+    fn drop(&mut self) {
+        if let Some(copy) = &self.copy {
+            let _ = fs::remove_dir_all(copy);
+        }
+    }
+}
+
+impl SledBackend {
+    fn tree(&self, space: Space) -> Result<sled::Tree, Box<dyn Error>> {
+        match space {
+            Space::Statuses => Ok((*self.db).clone()),
+            space => Ok(self.db.open_tree(space.name())?),
+        }
+    }
+}
+
+/// A batch in sled's own form.
+fn sled_batch(batch: Batch) -> sled::Batch {
+    let mut sled_batch = sled::Batch::default();
+    for (key, value) in batch.ops {
+        match value {
+            Some(value) => sled_batch.insert(key, value),
+            None => sled_batch.remove(key),
+        }
+    }
+    sled_batch
+}
+
+impl Backend for SledBackend {
+    fn commit(&self, transaction: Transaction) -> Result<(), Box<dyn Error>> {
+        if transaction.batches.is_empty() {
+            return Ok(());
+        }
+        let mut trees = Vec::new();
+        let mut batches = Vec::new();
+        for (space, batch) in transaction.batches {
+            trees.push(self.tree(space)?);
+            batches.push(sled_batch(batch));
+        }
+        trees
+            .as_slice()
+            .transaction(|trees| {
+                for (tree, batch) in trees.iter().zip(&batches) {
+                    tree.apply_batch(batch)?;
+                }
+                Ok::<_, sled::transaction::ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| format!("failed to commit: {:?}", e))?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(self.db.size_on_disk()?)
+    }
+
+    fn sled(&self) -> Option<&sled::Db> {
+        Some(&self.db)
+    }
+}
+
+struct SledStore(sled::Tree);
+
+impl StatusStore for SledStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.0.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn iter(&self) -> Result<Entries, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for r in self.0.iter() {
+            let (key, value) = r?;
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn batch(&self, batch: Batch) -> Result<(), Box<dyn Error>> {
+        self.0.apply_batch(sled_batch(batch))?;
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Entries, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for r in self.0.scan_prefix(prefix) {
+            let (key, value) = r?;
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn contains(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.0.contains_key(key)?)
+    }
+
+    fn len(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.0.len())
+    }
+}
+
+const JSON_FILE: &str = "db.json";
+
+/// Write a key so the file stays readable: UTF-8 is kept and other bytes,
+/// control characters and `%` are written as `%XX`.
+fn encode_key(key: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in key.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '%' || c.is_control() {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    encoded.push_str(&format!("%{:02X}", b));
+                }
+            } else {
+                encoded.push(c);
+            }
+        }
+        for b in chunk.invalid() {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut decoded = Vec::new();
+    let mut rest = key;
+    while let Some(i) = rest.find('%') {
+        decoded.extend_from_slice(&rest.as_bytes()[..i]);
+        let hex = rest
+            .get(i + 1..i + 3)
+            .ok_or_else(|| format!("bad escape in key {:?}", key))?;
+        decoded
+            .push(u8::from_str_radix(hex, 16).map_err(|_| format!("bad escape in key {:?}", key))?);
+        rest = &rest[i + 3..];
+    }
+    decoded.extend_from_slice(rest.as_bytes());
+    Ok(decoded)
+}
+
+/// One keyspace's entries.
+type Tree = BTreeMap<Vec<u8>, Vec<u8>>;
+
+type Trees = BTreeMap<String, Tree>;
+
+/// Every keyspace in db.json, read once when opened and written back in
+/// full, to a temporary file renamed over it, on each batch. The lock file
+/// keeps other writers out while it's open, as sled's lock does. Readers
+/// don't need it since the file is only ever replaced whole.
+struct JsonFile {
+    path: PathBuf,
+    trees: Mutex<Trees>,
+    _lock: Option<File>,
+}
+
+impl JsonFile {
+    fn open(dir: &Path, lock: bool) -> Result<JsonFile, Box<dyn Error>> {
+        let lock = if lock {
+            let lock = File::create(dir.join("db.json.lock"))?;
+            lock.try_lock()?;
+            Some(lock)
+        } else {
+            None
+        };
+        let path = dir.join(JSON_FILE);
+        let mut trees = Trees::new();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let file: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+                    serde_json::from_str(&text)
+                        .map_err(|e| format!("invalid database {}: {}", path.display(), e))?;
+                for (name, entries) in file {
+                    let tree = trees.entry(name).or_default();
+                    for (key, value) in entries {
+                        tree.insert(decode_key(&key)?, serde_json::to_vec(&value)?);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(JsonFile {
+            path,
+            trees: Mutex::new(trees),
+            _lock: lock,
+        })
+    }
+
+    fn write(&self, trees: &Trees) -> Result<(), Box<dyn Error>> {
+        let mut file = BTreeMap::new();
+        for (name, entries) in trees {
+            let mut values = BTreeMap::new();
+            for (key, value) in entries {
+                let value: serde_json::Value = serde_json::from_slice(value)
+                    .map_err(|_| "the json backend only stores JSON values")?;
+                values.insert(encode_key(key), value);
+            }
+            file.insert(name, values);
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut out = File::create(&tmp)?;
+        serde_json::to_writer_pretty(&mut out, &file)?;
+        out.write_all(b"\n")?;
+        out.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn trees(&self) -> Result<std::sync::MutexGuard<'_, Trees>, Box<dyn Error>> {
+        self.trees
+            .lock()
+            .map_err(|_| "the json database was poisoned".into())
+    }
+
+    /// Apply the batches to the trees they're named with and write the file
+    /// once.
+    fn apply(&self, batches: Vec<(&str, Batch)>) -> Result<(), Box<dyn Error>> {
+        if batches.iter().all(|(_, batch)| batch.ops.is_empty()) {
+            return Ok(());
+        }
+        let mut trees = self.trees()?;
+        let mut updated = trees.clone();
+        for (name, batch) in batches {
+            let tree = updated.entry(name.to_string()).or_default();
+            for (key, value) in batch.ops {
+                match value {
+                    Some(value) => tree.insert(key, value),
+                    None => tree.remove(&key),
+                };
+            }
+        }
+        self.write(&updated)?;
+        *trees = updated;
+        Ok(())
+    }
+}
+
+impl Backend for JsonFile {
+    fn commit(&self, transaction: Transaction) -> Result<(), Box<dyn Error>> {
+        self.apply(
+            transaction
+                .batches
+                .into_iter()
+                .map(|(space, batch)| (space.name(), batch))
+                .collect(),
+        )
+    }
+
+    /// Every batch is already written through.
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> Result<u64, Box<dyn Error>> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+struct JsonStore {
+    file: Arc<JsonFile>,
+    tree: &'static str,
+}
+
+impl StatusStore for JsonStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let trees = self.file.trees()?;
+        Ok(trees.get(self.tree).and_then(|t| t.get(key)).cloned())
+    }
+
+    fn iter(&self) -> Result<Entries, Box<dyn Error>> {
+        let trees = self.file.trees()?;
+        Ok(trees
+            .get(self.tree)
+            .map(|t| t.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    fn batch(&self, batch: Batch) -> Result<(), Box<dyn Error>> {
+        self.file.apply(vec![(self.tree, batch)])
+    }
+}
+
+/// The keyspaces, which transactions lock together.
+struct Memory(BTreeMap<Space, Arc<MemoryStore>>);
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory(
+            Space::ALL
+                .into_iter()
+                .map(|space| (space, Arc::default()))
+                .collect(),
+        )
+    }
+}
+
+impl Backend for Memory {
+    fn commit(&self, transaction: Transaction) -> Result<(), Box<dyn Error>> {
+        // Locked in key order, so commits can't deadlock each other.
+        let mut locked = Vec::new();
+        for (space, batch) in transaction.batches {
+            locked.push((self.0[&space].entries()?, batch));
+        }
+        for (entries, batch) in &mut locked {
+            apply(entries, std::mem::take(batch));
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(0)
+    }
+}
+
+#[derive(Default)]
+struct MemoryStore(Mutex<Tree>);
+
+impl MemoryStore {
+    fn entries(&self) -> Result<std::sync::MutexGuard<'_, Tree>, Box<dyn Error>> {
+        self.0
+            .lock()
+            .map_err(|_| "the memory database was poisoned".into())
+    }
+}
+
+impl StatusStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.entries()?.get(key).cloned())
+    }
+
+    fn iter(&self) -> Result<Entries, Box<dyn Error>> {
+        Ok(self
+            .entries()?
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn batch(&self, batch: Batch) -> Result<(), Box<dyn Error>> {
+        apply(&mut *self.entries()?, batch);
+        Ok(())
+    }
+}
+
+fn apply(entries: &mut Tree, batch: Batch) {
+    for (key, value) in batch.ops {
+        match value {
+            Some(value) => entries.insert(key, value),
+            None => entries.remove(&key),
+        };
+    }
+}
@@ -0,0 +1,280 @@
+//! Templates for laying out a status on one line, e.g.
+//! `{branch} {modified}M {untracked}?`.
+//!
+//! Placeholders are names in braces, and `{{` and `}}` are literal braces:
+//!
+//! - `path`, `branch`, `upstream`, `commit` (the short hash) and `subject`.
+//! - `remote` and `remote_url`, the name and URL of the remote.
+//! - `repo_id`, the main worktree of the repository, and `bare`, `shallow`
+//!   and `sparse`, `true` for bare repositories, shallow clones and sparse
+//!   checkouts.
+//! - `head_state`, one of `branch`, `detached` or `unborn`, and
+//!   `operation`, like `REBASE 2/5`.
+//! - `modified`, `added`, `deleted`, `renamed`, `copied`, `conflicted`,
+//!   `untracked` and `ignored`, counting the changes in each category. A code
+//!   like `AM` counts as both added and modified, and one like `UD` only as
+//!   conflicted.
+//! - `staged` and `unstaged` for the changes in the index and in the
+//!   worktree.
+//! - `code:XY` for the count of one porcelain code, e.g. `code:??`, and
+//!   `meta:KEY` for the metadata with that key.
+//! - `total` for every change, `ahead`, `behind` and `stashes`.
+//! - `submodules_modified` and `submodules_uninitialized`.
+//! - `updated` and `created` as RFC 3339 dates, and `updated_ago` and
+//!   `created_ago` like `5m ago`.
+//!
+//! Named templates live in the `templates` directory of the config
+//! directory, e.g. `templates/waybar.tmpl`.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::{clock, Remote, Status, Submodules};
+
+/// Read the template called name from the templates in dir.
+pub fn load(dir: &str, name: &str) -> Result<String, Box<dyn Error>> {
+    let path = Path::new(dir)
+        .join("templates")
+        .join(format!("{}.tmpl", name));
+    match fs::read_to_string(&path) {
+        Ok(template) => Ok(template.trim_end_matches(['\n', '\r']).to_string()),
+        Err(e) => Err(format!(
+            "can't read template {} from {}: {}",
+            name,
+            path.display(),
+            e
+        )
+        .into()),
+    }
+}
+
+/// The categories of codes, in the order they're shown in tables.
+pub const CATEGORIES: &[&str] = &[
+    "modified",
+    "added",
+    "deleted",
+    "renamed",
+    "copied",
+    "conflicted",
+    "untracked",
+    "ignored",
+];
+
+/// How many changes in status are in the category.
+pub fn category(status: &Status, name: &str) -> Option<u64> {
+    status.categories.get(name)
+}
+
+/// The value of the placeholder called name.
+pub fn placeholder(status: &Status, name: &str) -> Result<String, String> {
+    if let Some(code) = name.strip_prefix("code:") {
+        return Ok(status
+            .git_status
+            .get(code)
+            .copied()
+            .unwrap_or(0)
+            .to_string());
+    }
+    if let Some(key) = name.strip_prefix("meta:") {
+        return Ok(status.metadata.get(key).cloned().unwrap_or_default());
+    }
+    if let Some(count) = category(status, name) {
+        return Ok(count.to_string());
+    }
+    let submodule = |count: fn(&Submodules) -> u64| {
+        status
+            .submodules
+            .as_ref()
+            .map(|s| count(s).to_string())
+            .unwrap_or_default()
+    };
+    let remote = |field: fn(&Remote) -> &String| {
+        status
+            .remote
+            .as_ref()
+            .map(|r| field(r).clone())
+            .unwrap_or_default()
+    };
+    let time = |t: u64, format: fn(u64) -> String| if t > 0 { format(t) } else { String::new() };
+    Ok(match name {
+        "path" => status.path.clone(),
+        "branch" => status.branch.clone(),
+        "upstream" => status.upstream.clone().unwrap_or_default(),
+        "remote" => remote(|r| &r.name),
+        "remote_url" => remote(|r| &r.url),
+        "repo_id" => status.repo_id.clone().unwrap_or_default(),
+        "bare" => status.bare.to_string(),
+        "shallow" => status.shallow.to_string(),
+        "sparse" => status.sparse.to_string(),
+        "commit" => status
+            .commit
+            .as_ref()
+            .map(|c| c.hash.clone())
+            .unwrap_or_default(),
+        "subject" => status
+            .commit
+            .as_ref()
+            .map(|c| c.subject.clone())
+            .unwrap_or_default(),
+        "head_state" => status.head_state.as_str().to_string(),
+        "operation" => status.operation.clone().unwrap_or_default(),
+        "total" => status.changes().to_string(),
+        "ahead" => status.ahead.to_string(),
+        "behind" => status.behind.to_string(),
+        "stashes" => status.stashes.to_string(),
+        "submodules_modified" => submodule(|s| s.modified),
+        "submodules_uninitialized" => submodule(|s| s.uninitialized),
+        "updated" => time(status.updated_at, clock::format),
+        "created" => time(status.created_at, clock::format),
+        "updated_ago" => time(status.updated_at, clock::ago),
+        "created_ago" => time(status.created_at, clock::ago),
+        _ => return Err(format!("unknown placeholder {{{}}}", name)),
+    })
+}
+
+/// Fill in the placeholders in template from status.
+pub fn render(template: &str, status: &Status) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+        if let Some(stripped) = rest.strip_prefix(brace) {
+            out.push_str(brace);
+            rest = stripped;
+            continue;
+        }
+        if brace == "}" {
+            return Err(format!("unmatched }} in template {:?}", template));
+        }
+        let end = rest
+            .find('}')
+            .ok_or_else(|| format!("unclosed {{ in template {:?}", template))?;
+        out.push_str(&placeholder(status, &rest[..end])?);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        let porcelain = " M a\nM  b\nUD c\n?? d\n";
+        Status {
+            path: "/repo".to_string(),
+            branch: "main".to_string(),
+            git_status: [(" M", 1), ("M ", 1), ("UD", 1), ("??", 1)]
+                .into_iter()
+                .map(|(code, count)| (code.to_string(), count))
+                .collect(),
+            categories: crate::git::categorize_porcelain(porcelain),
+            ahead: 2,
+            metadata: [("owner".to_string(), "me".to_string())].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn placeholders() {
+        let status = status();
+        let table = [
+            ("{branch} +{ahead}", "main +2"),
+            ("{modified}M {untracked}?", "2M 1?"),
+            ("{staged}/{unstaged}", "1/1"),
+            // UD is a conflict, not a deletion.
+            ("{conflicted}U {deleted}D", "1U 0D"),
+            ("{code:??} {code:M } {code:AA}", "1 1 0"),
+            ("{meta:owner}{meta:missing}", "me"),
+            ("no placeholders", "no placeholders"),
+            ("", ""),
+        ];
+        for (template, expected) in table {
+            assert_eq!(
+                render(template, &status).unwrap(),
+                expected,
+                "{:?}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_empty() {
+        let status = status();
+        for name in [
+            "upstream",
+            "commit",
+            "subject",
+            "operation",
+            "remote",
+            "remote_url",
+            "repo_id",
+            "submodules_modified",
+            "updated",
+            "created_ago",
+        ] {
+            let template = format!("[{{{}}}]", name);
+            assert_eq!(render(&template, &status).unwrap(), "[]", "{}", name);
+        }
+    }
+
+    #[test]
+    fn braces_are_escaped() {
+        let status = status();
+        assert_eq!(render("{{branch}}", &status).unwrap(), "{branch}");
+        assert_eq!(render("{{{branch}}}", &status).unwrap(), "{main}");
+        assert_eq!(render("}}{{", &status).unwrap(), "}{");
+    }
+
+    #[test]
+    fn errors() {
+        let status = status();
+        let table = [
+            ("{nope}", "unknown placeholder {nope}"),
+            ("{}", "unknown placeholder {}"),
+            ("{branch", "unclosed { in template \"{branch\""),
+            ("{branch} {", "unclosed { in template \"{branch} {\""),
+            ("branch}", "unmatched } in template \"branch}\""),
+            ("{{branch}", "unmatched } in template \"{{branch}\""),
+        ];
+        for (template, expected) in table {
+            assert_eq!(
+                render(template, &status).unwrap_err(),
+                expected,
+                "{:?}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn named_templates() {
+        let dir = std::env::temp_dir().join(format!(
+            "git-status-tracker-templates-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        fs::write(dir.join("templates/bar.tmpl"), "{branch} {total}\r\n\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+
+        let bar = load(dir_str, "bar").unwrap();
+        assert_eq!(bar, "{branch} {total}");
+        assert_eq!(render(&bar, &status()).unwrap(), "main 4");
+        let missing = load(dir_str, "missing").unwrap_err().to_string();
+        assert!(
+            missing.starts_with(&format!(
+                "can't read template missing from {}: ",
+                dir.join("templates/missing.tmpl").display()
+            )),
+            "{}",
+            missing
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
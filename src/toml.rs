@@ -0,0 +1,459 @@
+//! Just enough TOML to read and write the export format.
+//!
+//! Values are converted through `serde_json::Value`. Nulls are omitted since
+//! TOML has no equivalent. Reading supports tables, arrays of tables, dotted
+//! and quoted keys, strings, integers, floats, booleans, arrays and inline
+//! tables, but not dates or multi-line strings.
+
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::{Map, Value};
+
+/// Render a JSON object as a TOML document.
+pub fn to_string(value: &Value) -> Result<String, String> {
+    let table = value
+        .as_object()
+        .ok_or("only objects can be written as TOML")?;
+    let mut out = String::new();
+    write_table(&mut out, &[], table, false);
+    Ok(out)
+}
+
+fn write_table(out: &mut String, prefix: &[String], table: &Map<String, Value>, array: bool) {
+    if !prefix.is_empty() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let header = prefix.iter().map(|k| key(k)).collect::<Vec<_>>().join(".");
+        if array {
+            let _ = writeln!(out, "[[{}]]", header);
+        } else {
+            let _ = writeln!(out, "[{}]", header);
+        }
+    }
+
+    for (k, v) in table {
+        if !v.is_object() && !is_table_array(v) && !v.is_null() {
+            let _ = writeln!(out, "{} = {}", key(k), inline(v));
+        }
+    }
+    for (k, v) in table {
+        let mut path = prefix.to_vec();
+        path.push(k.clone());
+        if let Value::Object(t) = v {
+            write_table(out, &path, t, false);
+        } else if is_table_array(v) {
+            for t in v
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_object)
+            {
+                write_table(out, &path, t, true);
+            }
+        }
+    }
+}
+
+fn is_table_array(v: &Value) -> bool {
+    matches!(v, Value::Array(a) if !a.is_empty() && a.iter().all(Value::is_object))
+}
+
+fn key(k: &str) -> String {
+    let bare = !k.is_empty()
+        && k.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        k.to_string()
+    } else {
+        quote(k)
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn inline(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => quote(s),
+        Value::Array(a) => {
+            let items = a
+                .iter()
+                .filter(|v| !v.is_null())
+                .map(inline)
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(t) => {
+            let items = t
+                .iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| format!("{} = {}", key(k), inline(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", items.join(", "))
+        }
+    }
+}
+
+/// Parse a TOML document into a JSON object.
+pub fn from_str(document: &str) -> Result<Value, String> {
+    let mut root = Map::new();
+    let mut current: Vec<String> = Vec::new();
+    for (number, line) in document.lines().enumerate() {
+        parse_line(&mut root, &mut current, line)
+            .map_err(|e| format!("line {}: {}", number + 1, e))?;
+    }
+    Ok(Value::Object(root))
+}
+
+fn parse_line(
+    root: &mut Map<String, Value>,
+    current: &mut Vec<String>,
+    line: &str,
+) -> Result<(), String> {
+    let mut chars = line.chars().peekable();
+    skip_space(&mut chars);
+    match chars.peek() {
+        None | Some('#') => return Ok(()),
+        Some('[') => {
+            chars.next();
+            let array = chars.next_if_eq(&'[').is_some();
+            let path = parse_key(&mut chars)?;
+            expect(&mut chars, ']')?;
+            if array {
+                expect(&mut chars, ']')?;
+            }
+            end_of_line(&mut chars)?;
+
+            let (last, parents) = path.split_last().ok_or("empty table name")?;
+            let parent = table_at(root, parents)?;
+            if array {
+                let entry = parent
+                    .entry(last.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                entry
+                    .as_array_mut()
+                    .ok_or_else(|| format!("{} is not an array of tables", last))?
+                    .push(Value::Object(Map::new()));
+            } else {
+                table_at(parent, std::slice::from_ref(last))?;
+            }
+            *current = path;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let path = parse_key(&mut chars)?;
+    expect(&mut chars, '=')?;
+    let value = parse_value(&mut chars)?;
+    end_of_line(&mut chars)?;
+
+    let (last, parents) = path.split_last().ok_or("empty key")?;
+    let table = table_at(table_at(root, current)?, parents)?;
+    if table.insert(last.clone(), value).is_some() {
+        return Err(format!("duplicate key {}", last));
+    }
+    Ok(())
+}
+
+/// Find the table at path, creating any missing tables along the way. Arrays
+/// of tables resolve to their last element.
+fn table_at<'a>(
+    mut table: &'a mut Map<String, Value>,
+    path: &[String],
+) -> Result<&'a mut Map<String, Value>, String> {
+    for k in path {
+        let entry = table
+            .entry(k.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        let entry = match entry {
+            Value::Array(a) => a.last_mut().ok_or_else(|| format!("{} is empty", k))?,
+            v => v,
+        };
+        table = entry
+            .as_object_mut()
+            .ok_or_else(|| format!("{} is not a table", k))?;
+    }
+    Ok(table)
+}
+
+fn skip_space(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    skip_space(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected '{}' but found '{}'", expected, c)),
+        None => Err(format!("expected '{}'", expected)),
+    }
+}
+
+fn end_of_line(chars: &mut Peekable<Chars>) -> Result<(), String> {
+    skip_space(chars);
+    match chars.next() {
+        None | Some('#') => Ok(()),
+        Some(c) => Err(format!("unexpected '{}'", c)),
+    }
+}
+
+fn parse_key(chars: &mut Peekable<Chars>) -> Result<Vec<String>, String> {
+    let mut path = Vec::new();
+    loop {
+        skip_space(chars);
+        let part = match chars.peek() {
+            Some('"') | Some('\'') => parse_string(chars)?,
+            _ => {
+                let mut part = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    part.push(c);
+                }
+                if part.is_empty() {
+                    return Err("expected a key".into());
+                }
+                part
+            }
+        };
+        path.push(part);
+        skip_space(chars);
+        if chars.next_if_eq(&'.').is_none() {
+            return Ok(path);
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let quote = chars.next().ok_or("expected a string")?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".into()),
+            Some(c) if c == quote => return Ok(s),
+            Some('\\') if quote == '"' => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some(u @ ('u' | 'U')) => {
+                    let len = if u == 'u' { 4 } else { 8 };
+                    let hex = chars.by_ref().take(len).collect::<String>();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid unicode escape \\{}{}", u, hex))?;
+                    s.push(c);
+                }
+                Some(c) => return Err(format!("invalid escape \\{}", c)),
+                None => return Err("unterminated string".into()),
+            },
+            Some(c) => s.push(c),
+        }
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_space(chars);
+    match chars.peek() {
+        Some('"') | Some('\'') => Ok(Value::String(parse_string(chars)?)),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                skip_space(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                items.push(parse_value(chars)?);
+                skip_space(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    expect(chars, ']')?;
+                    return Ok(Value::Array(items));
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut table = Map::new();
+            loop {
+                skip_space(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Ok(Value::Object(table));
+                }
+                let path = parse_key(chars)?;
+                expect(chars, '=')?;
+                let value = parse_value(chars)?;
+                let (last, parents) = path.split_last().ok_or("empty key")?;
+                table_at(&mut table, parents)?.insert(last.clone(), value);
+                skip_space(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    expect(chars, '}')?;
+                    return Ok(Value::Object(table));
+                }
+            }
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| !matches!(c, ',' | ']' | '}' | '#') && !c.is_whitespace())
+            {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "" => Err("expected a value".into()),
+                _ => {
+                    let number = word.replace('_', "");
+                    if let Ok(i) = number.parse::<i64>() {
+                        Ok(Value::from(i))
+                    } else if let Ok(u) = number.parse::<u64>() {
+                        Ok(Value::from(u))
+                    } else if let Some(f) = number
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                    {
+                        Ok(Value::Number(f))
+                    } else {
+                        Err(format!("invalid value {}", word))
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips() {
+        let value = json!({
+            "version": 1,
+            "statuses": [
+                { "path": "/a \"quoted\"\\path", "branch": "main", "git_status": { "??": 1, "M": 2 } },
+                { "path": "/b", "branch": "dev\tbranch", "tags": ["x", "y"], "git_status": {} }
+            ]
+        });
+        let document = to_string(&value).unwrap();
+        assert!(
+            document.starts_with("version = 1\n\n[[statuses]]\n"),
+            "{}",
+            document
+        );
+        assert!(
+            document.contains("[statuses.git_status]\n\"??\" = 1\n"),
+            "{}",
+            document
+        );
+        assert_eq!(from_str(&document).unwrap(), value);
+    }
+
+    #[test]
+    fn nulls_are_left_out() {
+        let document = to_string(&json!({ "a": null, "b": [1, null] })).unwrap();
+        assert_eq!(document, "b = [1]\n");
+    }
+
+    #[test]
+    fn quoting() {
+        let value = from_str(
+            r#"
+            "quoted key" = "a \"b\" \\ \n \u00e9 \U0001F33F"
+            'literal key' = 'C:\no\escapes'
+            bare-key_1 = ""
+            "#,
+        )
+        .unwrap();
+        assert_eq!(value["quoted key"], "a \"b\" \\ \n é 🌿");
+        assert_eq!(value["literal key"], "C:\\no\\escapes");
+        assert_eq!(value["bare-key_1"], "");
+    }
+
+    #[test]
+    fn comments() {
+        let value = from_str(
+            "# a comment\n\
+             a = 1 # after a value\n\
+             \n\
+             [t] # after a table\n\
+             b = \"# not a comment\"\n",
+        )
+        .unwrap();
+        assert_eq!(value, json!({ "a": 1, "t": { "b": "# not a comment" } }));
+    }
+
+    #[test]
+    fn tables() {
+        let value = from_str(
+            "[a.b]\n\
+             c = 1\n\
+             d.e = true\n\
+             [[list]]\n\
+             x = 1\n\
+             [[list]]\n\
+             x = 2\n\
+             [list.inner]\n\
+             y = 3\n\
+             [inline]\n\
+             t = { k = \"v\", n.m = [1.5, -2, 1_000] }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "a": { "b": { "c": 1, "d": { "e": true } } },
+                "list": [{ "x": 1 }, { "x": 2, "inner": { "y": 3 } }],
+                "inline": { "t": { "k": "v", "n": { "m": [1.5, -2, 1000] } } }
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        let error = |document| from_str(document).unwrap_err();
+        assert_eq!(error("a = \"open"), "line 1: unterminated string");
+        assert_eq!(error("a = 1\na = 2"), "line 2: duplicate key a");
+        assert_eq!(error("a 1"), "line 1: expected '=' but found '1'");
+        assert_eq!(error("a = nope"), "line 1: invalid value nope");
+        assert_eq!(error("a ="), "line 1: expected a value");
+        assert_eq!(error("= 1"), "line 1: expected a key");
+        assert_eq!(error("[a"), "line 1: expected ']'");
+        assert_eq!(error("a = 1 2"), "line 1: unexpected '2'");
+        assert_eq!(error("a = \"\\q\""), "line 1: invalid escape \\q");
+        assert_eq!(error("a = 1\n[a]"), "line 2: a is not a table");
+        assert_eq!(error("[a]\n[[a]]"), "line 2: a is not an array of tables");
+        assert_eq!(error("a = [1, 2"), "line 1: expected ']'");
+        assert!(to_string(&json!([1])).is_err());
+    }
+}
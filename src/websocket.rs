@@ -0,0 +1,218 @@
+//! The server side of RFC 6455 WebSockets, just enough to push text
+//! messages: the handshake, unmasked text frames and pings. What clients
+//! send after the handshake is only read to notice them closing.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+/// The GUID the handshake appends to the client's key.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The response accepting an upgrade with the client's Sec-WebSocket-Key.
+pub fn handshake(key: &str) -> String {
+    let accept = base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()));
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )
+}
+
+/// The opcodes of the frames sent or read.
+const TEXT: u8 = 0x1;
+pub const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+
+/// Write text as one frame.
+pub fn send(writer: &mut impl Write, text: &str) -> Result<(), Box<dyn Error>> {
+    write_frame(writer, TEXT, text.as_bytes())
+}
+
+/// Write a ping, which fails once the client has gone.
+pub fn ping(writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    write_frame(writer, PING, &[])
+}
+
+/// Answer the client's close.
+pub fn close(writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    write_frame(writer, CLOSE, &[])
+}
+
+/// Write payload as one final frame with opcode.
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a frame from the client, skipping its payload, and return its
+/// opcode, or None when the connection was closed before one started.
+pub fn read_frame(reader: &mut impl Read) -> Result<Option<u8>, Box<dyn Error>> {
+    let mut header = [0; 2];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    // Clients mask their frames with 4 bytes after the length.
+    let masked = if header[1] & 0x80 != 0 { 4 } else { 0 };
+    let skipped = io::copy(&mut reader.take(masked + len), &mut io::sink())?;
+    if skipped < masked + len {
+        return Err("connection closed in the middle of a frame".into());
+    }
+    Ok(Some(header[0] & 0x0f))
+}
+
+/// The SHA-1 digest of data, which the handshake needs and nothing else
+/// should use.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, h) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_digests() {
+        let hex = |d: [u8; 20]| d.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn handshake_accepts_the_rfc_example() {
+        assert!(handshake("dGhlIHNhbXBsZSBub25jZQ==")
+            .contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn frames_carry_their_length() {
+        let mut frame = Vec::new();
+        send(&mut frame, "hi").unwrap();
+        assert_eq!(frame, b"\x81\x02hi");
+        let mut frame = Vec::new();
+        send(&mut frame, &"x".repeat(300)).unwrap();
+        assert_eq!(&frame[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(frame.len(), 304);
+        let mut frame = Vec::new();
+        ping(&mut frame).unwrap();
+        assert_eq!(frame, b"\x89\x00");
+    }
+
+    #[test]
+    fn client_frames_are_skipped() {
+        // A masked text frame, a masked close with a status code and then
+        // nothing.
+        let mut frames: &[u8] = b"\x81\x82abcdxy\x88\x82abcd\x03\xe8";
+        assert_eq!(read_frame(&mut frames).unwrap(), Some(TEXT));
+        assert_eq!(read_frame(&mut frames).unwrap(), Some(CLOSE));
+        assert_eq!(read_frame(&mut frames).unwrap(), None);
+        let mut cut: &[u8] = b"\x81\x85abcdxy";
+        assert!(read_frame(&mut cut).is_err());
+    }
+}
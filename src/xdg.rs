@@ -0,0 +1,96 @@
+//! Where files go under the XDG Base Directory spec: the config and
+//! templates in `$XDG_CONFIG_HOME/git-status-tracker` and the database in
+//! `$XDG_DATA_HOME/git-status-tracker`, which default to `~/.config` and
+//! `~/.local/share`. The database used to live with the config, so it's
+//! moved over the first time it's looked for.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::compact::is_sled_file;
+
+const NAME: &str = "git-status-tracker";
+
+/// The directory in the XDG variable, or in home when it's unset or not
+/// absolute, which the spec says to ignore.
+fn base(var: &str, home: &[&str]) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(dir) = env::var_os(var).map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+    }
+    let mut dir = dirs::home_dir().ok_or("can't find the home directory")?;
+    dir.extend(home);
+    Ok(dir)
+}
+
+/// The directory with the config and templates.
+pub fn config_dir() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(base("XDG_CONFIG_HOME", &[".config"])?.join(NAME))
+}
+
+/// Whether dir holds a sled database.
+fn has_database(dir: &Path) -> bool {
+    dir.join("conf").exists()
+}
+
+/// Create dir if it's missing and make it readable by its owner alone, since
+/// the paths and branches in a database say what someone is working on.
+pub fn create_private(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let mut permissions = fs::metadata(dir)?.permissions();
+    if permissions.mode() & 0o077 != 0 {
+        permissions.set_mode(permissions.mode() & 0o700);
+        fs::set_permissions(dir, permissions)?;
+    }
+    Ok(())
+}
+
+/// Move the database in old to new. Nothing is moved unless every file can
+/// be, e.g. when they're on different filesystems.
+fn migrate(old: &Path, new: &Path) -> Result<(), Box<dyn Error>> {
+    create_private(new)?;
+    let mut moved = Vec::new();
+    for entry in fs::read_dir(old)? {
+        let name = entry?.file_name();
+        if !is_sled_file(&name.to_string_lossy()) {
+            continue;
+        }
+        if let Err(e) = fs::rename(old.join(&name), new.join(&name)) {
+            for name in moved {
+                let _ = fs::rename(new.join(&name), old.join(&name));
+            }
+            return Err(e.into());
+        }
+        moved.push(name);
+    }
+    Ok(())
+}
+
+/// The directory with the database, moving a database out of the config
+/// directory into it if there's one there. When it can't be moved the old
+/// location keeps being used.
+pub fn data_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let dir = base("XDG_DATA_HOME", &[".local", "share"])?.join(NAME);
+    let old = config_dir()?;
+    if old != dir && !has_database(&dir) && has_database(&old) {
+        if let Err(e) = migrate(&old, &dir) {
+            eprintln!(
+                "can't move the database from {} to {}, using it where it is: {}",
+                old.display(),
+                dir.display(),
+                e
+            );
+            return Ok(old);
+        }
+        eprintln!(
+            "moved the database from {} to {}",
+            old.display(),
+            dir.display()
+        );
+    }
+    Ok(dir)
+}
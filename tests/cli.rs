@@ -0,0 +1,1263 @@
+//! Runs the subcommands against a fresh database in a temporary directory,
+//! once for each backend that keeps it on disk, and the ones the daemon
+//! answers against the memory backend.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A home directory of its own, removed when dropped.
+struct Tracker {
+    home: PathBuf,
+    backend: &'static str,
+    daemon: Option<Child>,
+}
+
+impl Tracker {
+    fn new(backend: &'static str) -> Tracker {
+        let home = env::temp_dir().join(format!(
+            "git-status-tracker-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        if home.exists() {
+            fs::remove_dir_all(&home).unwrap();
+        }
+        fs::create_dir_all(home.join("tmp")).unwrap();
+        Tracker {
+            home,
+            backend,
+            daemon: None,
+        }
+    }
+
+    fn db(&self) -> PathBuf {
+        self.home.join("data/git-status-tracker")
+    }
+
+    /// What's left in the TMPDIR the subcommands are run with.
+    fn leftovers(&self) -> Vec<PathBuf> {
+        fs::read_dir(self.home.join("tmp"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_git-status-tracker"));
+        command
+            .env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join("config"))
+            .env("XDG_DATA_HOME", self.home.join("data"))
+            .env("TMPDIR", self.home.join("tmp"))
+            .env_remove("GIT_STATUS_TRACKER_DB")
+            .env_remove("GST_PROFILE")
+            .env_remove("GST_SOCKET")
+            .args(["--backend", self.backend, "--color", "never"])
+            .args(args);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Run args, which must succeed, and return stdout.
+    fn ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run args with input on stdin, which must succeed, and return stdout.
+    fn ok_with(&self, args: &[&str], input: &str) -> String {
+        use std::io::Write;
+
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run args and return the exit code and stderr.
+    fn fail(&self, args: &[&str]) -> (i32, String) {
+        let output = self.run(args);
+        (
+            output.status.code().unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    }
+
+    fn put(&self, path: &str, branch: &str, git_status: &str) {
+        self.ok(&[
+            "put",
+            "--path",
+            path,
+            "--branch",
+            branch,
+            "--git-status",
+            git_status,
+        ]);
+    }
+
+    fn field(&self, path: &str, field: &str) -> String {
+        self.ok(&["get", "--path", path, "--field", field])
+            .trim_end()
+            .to_string()
+    }
+
+    /// Start a daemon and wait for its socket.
+    fn start_daemon(&mut self) {
+        self.serve(&["daemon"], &self.db().join("daemon.sock"));
+    }
+
+    /// Start a daemon with args and wait for it to listen on socket.
+    fn serve(&mut self, args: &[&str], socket: &Path) {
+        let daemon = self
+            .command(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.daemon = Some(daemon);
+        for _ in 0..100 {
+            if socket.exists() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the daemon never listened on {}", socket.display());
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        if let Some(daemon) = &mut self.daemon {
+            let _ = daemon.kill();
+            let _ = daemon.wait();
+        }
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
+
+/// A git repository with a commit and an untracked file.
+fn repository(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    git(&["init", "-b", "main"]);
+    fs::write(dir.join("a"), "a").unwrap();
+    git(&["add", "a"]);
+    git(&["commit", "-m", "a"]);
+    fs::write(dir.join("b"), "b").unwrap();
+}
+
+fn put_and_get(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo/", "main", "2 M|1 ??");
+    assert_eq!(t.field("/repo", "branch"), "main");
+    assert_eq!(t.field("/repo/", "branch"), "main");
+    assert_eq!(t.field("/repo", "git-status"), "1 ?? | 2 M");
+    let json = t.ok(&["get", "--path", "/repo", "--json"]);
+    assert!(json.contains(r#""path":"/repo""#), "{}", json);
+    // The default output is only the branch and counts, as it always was.
+    assert_eq!(t.ok(&["get", "--path", "/repo"]), "main\n1 ?? | 2 M \n");
+    assert_eq!(t.ok(&["list"]), "/repo: main {\"??\": 1, \"M\": 2}\n");
+    let times = t.ok(&["get", "--path", "/repo", "--show-times"]);
+    assert!(times.contains("updated "), "{}", times);
+}
+
+fn get_missing(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    let (code, stderr) = t.fail(&["get", "--path", "/nowhere"]);
+    assert_eq!(code, 2);
+    assert_eq!(stderr, "no status found for /nowhere\n");
+    // Exiting with a code still removes the copy a read-only open makes.
+    assert_eq!(t.leftovers(), Vec::<PathBuf>::new());
+    assert_eq!(
+        t.ok(&["get", "--path", "/nowhere", "--default", "-"]),
+        "-\n"
+    );
+}
+
+fn get_quiet(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/clean", "main", "");
+    t.put("/dirty", "main", "1 M");
+    assert_eq!(t.ok(&["get", "--path", "/clean", "--quiet"]), "");
+    assert_eq!(t.fail(&["get", "--path", "/dirty", "--quiet"]).0, 1);
+    assert_eq!(t.fail(&["get", "--path", "/nowhere", "--quiet"]).0, 2);
+}
+
+fn put_if_changed(backend: &'static str) {
+    let t = Tracker::new(backend);
+    let args = ["put", "--path", "/repo", "--branch", "main", "--if-changed"];
+    t.ok(&args);
+    assert_eq!(t.fail(&args).0, 1);
+}
+
+fn exists(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    t.ok(&["exists", "--path", "/repo/"]);
+    assert_eq!(t.fail(&["exists", "--path", "/nowhere"]).0, 2);
+}
+
+fn delete(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    t.ok(&["delete", "--path", "/repo/"]);
+    let (code, stderr) = t.fail(&["delete", "--path", "/repo"]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "Error: no status found for /repo\n");
+}
+
+fn list_and_count(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/b", "main", "");
+    t.put("/a", "dev", "1 M");
+    let list = t.ok(&["list"]);
+    let lines = list.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("/a: dev"), "{}", list);
+    assert!(lines[1].starts_with("/b: main"), "{}", list);
+    assert_eq!(t.ok(&["count"]), "2\n");
+    let found = t.ok(&["search", "--contains", "a"]);
+    assert!(found.starts_with("/a\n"), "{}", found);
+    assert!(!found.contains("/b"), "{}", found);
+}
+
+fn rename(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/old", "main", "");
+    t.put("/old", "main", "1 M");
+    t.put("/taken", "main", "");
+    t.ok(&["rename", "--from", "/old/", "--to", "/new"]);
+    assert_eq!(t.field("/new", "branch"), "main");
+    assert_eq!(t.fail(&["get", "--path", "/old"]).0, 2);
+    assert_eq!(t.ok(&["history", "--path", "/new"]).lines().count(), 2);
+    let (code, stderr) = t.fail(&["rename", "--from", "/new", "--to", "/taken"]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "Error: a status already exists for /taken\n");
+}
+
+fn history_and_diff(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    t.put("/repo", "main", "");
+    t.put("/repo", "dev", "1 M");
+    assert_eq!(t.ok(&["history", "--path", "/repo"]).lines().count(), 2);
+    t.put("/repository", "main", "");
+    assert_eq!(t.ok(&["history", "--path", "/repo"]).lines().count(), 2);
+    let diff = t.ok(&["diff", "--path", "/repo"]);
+    assert!(diff.contains("dev"), "{}", diff);
+}
+
+fn put_stdin(backend: &'static str) {
+    use std::io::Write;
+
+    let t = Tracker::new(backend);
+    let mut put = t
+        .command(&["put", "--stdin"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = put.stdin.take().unwrap();
+    for i in 0..50 {
+        writeln!(stdin, "/repo{}\tmain\t1 M", i).unwrap();
+    }
+    drop(stdin);
+    assert!(put.wait().unwrap().success());
+    assert_eq!(t.ok(&["count"]), "50\n");
+    assert_eq!(t.field("/repo49", "git-status"), "1 M");
+    assert_eq!(t.ok(&["history", "--path", "/repo7"]).lines().count(), 1);
+}
+
+fn tag_and_untag(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/work", "main", "");
+    t.put("/home", "main", "");
+    t.ok(&["tag", "--path", "/work", "--tag", "work"]);
+    assert!(t.ok(&["list", "--tag", "work"]).starts_with("/work:"));
+    assert_eq!(t.ok(&["list", "--tag", "work"]).lines().count(), 1);
+    t.ok(&["untag", "--path", "/work", "--tag", "work"]);
+    assert_eq!(t.ok(&["list", "--tag", "work"]), "");
+}
+
+fn unset_and_touch(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "1 M");
+    t.ok(&["unset", "--path", "/repo", "--field", "git-status"]);
+    assert_eq!(t.field("/repo", "git-status"), "");
+    assert_eq!(t.field("/repo", "branch"), "main");
+    t.ok(&["touch", "--path", "/repo"]);
+    assert_eq!(t.ok(&["stale"]), "");
+
+    // Conflicts go with the counts they're from.
+    t.put("/conflicted", "main", "2 UU");
+    assert!(t.ok(&["list", "--conflicted"]).contains("!2"));
+    t.ok(&["unset", "--path", "/conflicted", "--field", "git-status"]);
+    assert_eq!(t.ok(&["list", "--conflicted"]), "");
+}
+
+fn clear(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    let (code, stderr) = t.fail(&["clear"]);
+    assert_eq!(code, 3);
+    assert!(stderr.contains("--yes"), "{}", stderr);
+    t.ok(&["clear", "--yes"]);
+    assert_eq!(t.ok(&["count"]), "0\n");
+}
+
+fn prune(backend: &'static str) {
+    let t = Tracker::new(backend);
+    let here = t.home.to_string_lossy().to_string();
+    t.put(&here, "main", "");
+    t.put("/nowhere", "main", "");
+    assert_eq!(t.ok(&["prune", "--dry-run"]).lines().count(), 2);
+    assert_eq!(t.ok(&["count"]), "2\n");
+    t.ok(&["prune"]);
+    assert_eq!(t.ok(&["count"]), "1\n");
+    t.ok(&["exists", "--path", &here]);
+}
+
+fn export_and_import(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/a", "main", "1 M");
+    t.put("/b", "dev", "");
+    let export = t.home.join("export.json");
+    t.ok(&["export", "--output", export.to_str().unwrap()]);
+    t.ok(&["clear", "--yes"]);
+    assert_eq!(
+        t.ok(&["import", "--input", export.to_str().unwrap()]),
+        "created 2, updated 0, removed 0 statuses\n"
+    );
+    assert_eq!(t.field("/a", "git-status"), "1 M");
+    assert_eq!(t.field("/b", "branch"), "dev");
+}
+
+fn backup_and_restore(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    t.put("/repo", "dev", "");
+    let backup = t.home.join("backup.json");
+    t.ok(&["backup", "--output", backup.to_str().unwrap()]);
+    t.ok(&["clear", "--yes"]);
+    t.ok(&["restore", "--input", backup.to_str().unwrap()]);
+    assert_eq!(t.field("/repo", "branch"), "dev");
+    assert_eq!(t.ok(&["history", "--path", "/repo"]).lines().count(), 2);
+}
+
+fn merge(backend: &'static str) {
+    let t = Tracker::new(backend);
+    let mut other = Tracker::new(backend);
+    t.put("/mine", "main", "");
+    other.put("/theirs", "dev", "");
+    // The other database is only read, so its daemon can keep it open.
+    other.start_daemon();
+    let output = t.ok(&["merge", "--other", other.db().to_str().unwrap()]);
+    assert_eq!(output, "created 1, updated 0, kept 0 statuses\n");
+    assert_eq!(t.field("/theirs", "branch"), "dev");
+    let (code, _) = t.fail(&["merge", "--other", "/nowhere"]);
+    assert_eq!(code, 3);
+}
+
+fn doctor_and_stats(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/repo", "main", "2 M");
+    assert_eq!(t.ok(&["doctor"]), "checked 1 statuses, found 0 problems\n");
+    let stats = t.ok(&["stats"]);
+    assert!(stats.contains("repositories: 1"), "{}", stats);
+    assert!(stats.contains("changes: 2"), "{}", stats);
+}
+
+fn summary(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.put("/clean", "main", "");
+    t.put("/dirty", "main", "3 M");
+    let summary = t.ok(&["summary"]);
+    assert!(summary.contains("2 repos"), "{}", summary);
+    assert!(summary.contains("1 dirty"), "{}", summary);
+    assert_eq!(t.fail(&["summary", "--quiet"]).0, 1);
+}
+
+fn scan_and_refresh(backend: &'static str) {
+    let t = Tracker::new(backend);
+    let repo = t.home.join("src/repo");
+    repository(&repo);
+    t.ok(&["scan", "--root", t.home.join("src").to_str().unwrap()]);
+    let path = repo.to_str().unwrap();
+    assert_eq!(t.field(path, "branch"), "main");
+    assert_eq!(t.field(path, "git-status"), "1 ??");
+    fs::remove_file(repo.join("b")).unwrap();
+    t.ok(&["refresh", "--all"]);
+    assert_eq!(t.field(path, "git-status"), "");
+}
+
+fn watch(backend: &'static str) {
+    let mut t = Tracker::new(backend);
+    fs::create_dir_all(t.home.join("config/git-status-tracker")).unwrap();
+    fs::write(
+        t.home.join("config/git-status-tracker/config.json"),
+        r#"{"max_entries": 1}"#,
+    )
+    .unwrap();
+    t.put("/old", "main", "");
+    let repo = t.home.join("src/repo");
+    repository(&repo);
+    let root = t.home.join("src");
+    let watch = t
+        .command(&["watch", "--root", root.to_str().unwrap(), "--interval", "1"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    t.daemon = Some(watch);
+    let path = repo.to_str().unwrap();
+    let until = |want: &str| {
+        for _ in 0..100 {
+            let output = t.run(&["get", "--path", path, "--field", "git-status"]);
+            if String::from_utf8_lossy(&output.stdout).trim_end() == want {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("{} never had the status {}", path, want);
+    };
+    until("1 ??");
+    // Watching keeps to the config's max_entries.
+    assert_eq!(t.ok(&["count"]), "1\n");
+    fs::write(repo.join("c"), "c").unwrap();
+    until("2 ??");
+}
+
+fn ttl(backend: &'static str) {
+    let t = Tracker::new(backend);
+    t.ok(&["put", "--path", "/brief", "--branch", "main", "--ttl", "1s"]);
+    t.put("/kept", "main", "");
+    // Updates keep the ttl.
+    t.put("/brief", "dev", "");
+    thread::sleep(Duration::from_millis(2100));
+    assert_eq!(t.fail(&["get", "--path", "/brief"]).0, 2);
+    assert_eq!(t.fail(&["exists", "--path", "/brief"]).0, 2);
+    assert_eq!(t.fail(&["delete", "--path", "/brief"]).0, 3);
+    // History goes with the status once something writes.
+    t.put("/other", "main", "");
+    assert_eq!(t.ok(&["history", "--path", "/brief"]), "");
+    assert_eq!(t.field("/kept", "branch"), "main");
+
+    fs::create_dir_all(t.home.join("config/git-status-tracker")).unwrap();
+    fs::write(
+        t.home.join("config/git-status-tracker/config.json"),
+        r#"{"ttl": "1s"}"#,
+    )
+    .unwrap();
+    let list = t.ok(&["list"]);
+    assert!(!list.contains("/kept"), "{}", list);
+}
+
+fn max_entries(backend: &'static str) {
+    let t = Tracker::new(backend);
+    fs::create_dir_all(t.home.join("config/git-status-tracker")).unwrap();
+    fs::write(
+        t.home.join("config/git-status-tracker/config.json"),
+        r#"{"max_entries": 2}"#,
+    )
+    .unwrap();
+    t.put("/oldest", "main", "");
+    thread::sleep(Duration::from_millis(1100));
+    t.put("/b", "main", "");
+    t.put("/a", "main", "");
+    assert_eq!(t.ok(&["count"]), "2\n");
+    assert_eq!(t.fail(&["get", "--path", "/oldest"]).0, 2);
+    assert_eq!(t.ok(&["history", "--path", "/oldest"]), "");
+    t.put("/c", "main", "");
+    assert_eq!(t.ok(&["count"]), "2\n");
+    t.ok(&["exists", "--path", "/c"]);
+}
+
+fn no_wait(backend: &'static str) {
+    let mut t = Tracker::new(backend);
+    t.put("/repo", "main", "");
+    t.start_daemon();
+    let started = std::time::Instant::now();
+    let (code, err) = t.fail(&["--no-wait", "rename", "--from", "/repo", "--to", "/moved"]);
+    assert_eq!(code, 3);
+    assert!(!err.contains("attempts"), "{}", err);
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+fn broker(backend: &'static str) {
+    let t = Tracker::new(backend);
+    fs::create_dir_all(t.home.join("config/git-status-tracker")).unwrap();
+    fs::write(
+        t.home.join("config/git-status-tracker/config.json"),
+        r#"{"broker": {"idle": "1s"}}"#,
+    )
+    .unwrap();
+    let socket = t.db().join("daemon.sock");
+    t.put("/repo", "main", "");
+    assert!(socket.exists());
+    assert_eq!(t.field("/repo", "branch"), "main");
+    t.ok(&["delete", "--path", "/repo"]);
+    t.put("/repo", "main", "");
+    // Writes the broker can't answer make it step aside.
+    t.ok(&["rename", "--from", "/repo", "--to", "/moved"]);
+    assert!(!socket.exists());
+    t.put("/other", "main", "");
+    for _ in 0..100 {
+        if !socket.exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!socket.exists(), "the broker never exited");
+    assert_eq!(t.ok(&["count"]), "2\n");
+
+    // Stepping aside waits for the broker to release the lock, so even a
+    // --no-wait open straight after it succeeds.
+    for i in 0..5 {
+        t.put("/x", "main", "");
+        let to = format!("/x{}", i);
+        t.ok(&["--no-wait", "rename", "--from", "/x", "--to", &to]);
+    }
+    // Puts that land as the broker goes idle are answered before it exits.
+    for after in [950, 1000, 1050, 1100] {
+        thread::sleep(Duration::from_millis(after));
+        t.put(&format!("/idle{}", after), "main", "");
+    }
+    assert_eq!(t.ok(&["count"]), "11\n");
+}
+
+fn rotating_backups(backend: &'static str) {
+    let t = Tracker::new(backend);
+    fs::create_dir_all(t.home.join("config/git-status-tracker")).unwrap();
+    fs::write(
+        t.home.join("config/git-status-tracker/config.json"),
+        r#"{"backups": {"every": 2, "keep": 2}}"#,
+    )
+    .unwrap();
+    let backups = t.db().join("backups");
+    fs::create_dir_all(&backups).unwrap();
+    for old in ["2000-01-01T00:00:00Z.backup", "2000-01-02T00:00:00Z.backup"] {
+        fs::write(backups.join(old), "").unwrap();
+    }
+    t.put("/repo", "main", "");
+    assert_eq!(t.ok(&["restore", "--list"]).lines().count(), 2);
+    t.put("/other", "main", "");
+    let list = t.ok(&["restore", "--list"]);
+    let listed = list.lines().collect::<Vec<_>>();
+    assert_eq!(listed.len(), 2);
+    assert!(
+        listed[0].ends_with("2000-01-02T00:00:00Z.backup"),
+        "{}",
+        list
+    );
+    t.ok(&["delete", "--path", "/repo"]);
+    t.ok(&["restore", "--input", listed[1]]);
+    assert_eq!(t.ok(&["count"]), "2\n");
+}
+
+fn backups_in_the_same_second(backend: &'static str) {
+    let t = Tracker::new(backend);
+    let config = t.home.join("config/git-status-tracker/config.json");
+    fs::create_dir_all(config.parent().unwrap()).unwrap();
+    fs::write(&config, r#"{"backups": {"every": 1, "keep": 3}}"#).unwrap();
+    for path in ["/a", "/b", "/c", "/d"] {
+        t.put(path, "main", "");
+    }
+    let list = t.ok(&["restore", "--list"]);
+    let listed = list.lines().collect::<Vec<_>>();
+    assert_eq!(listed.len(), 3, "{}", list);
+    // Each backup has one more status than the one before, so none were
+    // overwritten.
+    for (i, backup) in listed.iter().enumerate() {
+        let text = fs::read_to_string(backup).unwrap();
+        let (_, body) = text.split_once('\n').unwrap();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body["statuses"].as_array().unwrap().len(),
+            i + 2,
+            "{}",
+            backup
+        );
+    }
+
+    fs::write(&config, r#"{"backups": {"every": 1, "keep": 0}}"#).unwrap();
+    let (code, stderr) = t.fail(&["put", "--path", "/e", "--branch", "main"]);
+    assert_eq!(code, 3);
+    assert!(stderr.contains("keep at least 1"), "{}", stderr);
+}
+
+fn read_only(backend: &'static str) {
+    let t = Tracker::new(backend);
+    assert_eq!(t.fail(&["get", "--path", "/repo"]).0, 2);
+    t.put("/repo", "main", "");
+    let (code, stderr) = t.fail(&["--read-only", "put", "--path", "/repo", "--branch", "dev"]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "Error: the database is open read-only\n");
+    assert_eq!(t.field("/repo", "branch"), "main");
+    assert_eq!(
+        t.ok(&["--read-only", "get", "--path", "/repo", "--field", "branch"]),
+        "main\n"
+    );
+}
+
+fn usage_error(backend: &'static str) {
+    let t = Tracker::new(backend);
+    assert_eq!(t.fail(&["get", "--no-such-flag"]).0, 3);
+}
+
+/// Generate a test of each function for each backend on disk.
+macro_rules! backends {
+    ($($name:ident),* $(,)?) => {
+        mod sled {
+            $(#[test] fn $name() { super::$name("sled") })*
+        }
+        mod json {
+            $(#[test] fn $name() { super::$name("json") })*
+        }
+    };
+}
+
+backends!(
+    put_and_get,
+    get_missing,
+    get_quiet,
+    put_if_changed,
+    exists,
+    delete,
+    list_and_count,
+    rename,
+    history_and_diff,
+    put_stdin,
+    tag_and_untag,
+    unset_and_touch,
+    clear,
+    prune,
+    export_and_import,
+    backup_and_restore,
+    merge,
+    doctor_and_stats,
+    summary,
+    scan_and_refresh,
+    watch,
+    ttl,
+    max_entries,
+    broker,
+    no_wait,
+    rotating_backups,
+    backups_in_the_same_second,
+    read_only,
+    usage_error,
+);
+
+#[test]
+fn compact_only_sled() {
+    let t = Tracker::new("sled");
+    t.put("/repo", "main", "");
+    assert!(t.ok(&["compact"]).starts_with("compacted from"));
+    assert_eq!(t.field("/repo", "branch"), "main");
+
+    let t = Tracker::new("json");
+    let (code, stderr) = t.fail(&["compact"]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "Error: only sled databases can be compacted\n");
+}
+
+#[test]
+fn corruption_is_set_aside() {
+    let t = Tracker::new("json");
+    t.put("/good", "main", "");
+    t.put("/bad", "main", "");
+    let file = t.db().join("db.json");
+    let mut db: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+    db["statuses"]["/bad"] = "garbage".into();
+    fs::write(&file, db.to_string()).unwrap();
+
+    let output = t.run(&["list"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("/good"));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("skipping /bad:"));
+    assert_eq!(t.fail(&["doctor"]).0, 3);
+    let fixed = t.ok(&["doctor", "--fix"]);
+    assert!(fixed.contains("1 values are set aside"), "{}", fixed);
+    assert!(t.run(&["list"]).stderr.is_empty());
+    let mut db: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(db["quarantine"]["statuses%00/bad"], "garbage");
+    assert!(db["statuses"].get("/bad").is_none());
+
+    // Rebuilding sets aside what it can't read in the same write.
+    db["statuses"]["/worse"] = "rubbish".into();
+    fs::write(&file, db.to_string()).unwrap();
+    let rebuilt = t.ok(&["doctor", "--rebuild"]);
+    assert!(rebuilt.contains("rebuilt 1 statuses"), "{}", rebuilt);
+    let db: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(db["quarantine"]["statuses%00/bad"], "garbage");
+    assert_eq!(db["quarantine"]["statuses%00/worse"], "rubbish");
+    assert!(db["statuses"].get("/worse").is_none());
+}
+
+#[test]
+fn memory_through_daemon() {
+    let mut t = Tracker::new("memory");
+    t.start_daemon();
+    t.put("/repo/", "main", "1 M");
+    assert_eq!(t.field("/repo", "branch"), "main");
+    assert!(t.ok(&["list"]).starts_with("/repo: main"));
+    assert!(t.ok(&["summary"]).contains("1 dirty"));
+    assert_eq!(t.fail(&["get", "--path", "/nowhere"]).0, 2);
+}
+
+#[test]
+fn serve_on_a_socket() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut t = Tracker::new("memory");
+    let socket = t.home.join("cache/gst.sock");
+    let path = socket.to_str().unwrap().to_string();
+    t.serve(&["serve", "--socket", &path], &socket);
+    let mode = fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    t.ok(&[
+        "--socket", &path, "put", "--path", "/repo", "--branch", "main",
+    ]);
+    let get = t
+        .command(&["get", "--path", "/repo", "--field", "branch"])
+        .env("GST_SOCKET", &path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(get.stdout).unwrap(), "main\n");
+    t.ok(&["--socket", &path, "delete", "--path", "/repo"]);
+    assert_eq!(t.fail(&["--socket", &path, "get", "--path", "/repo"]).0, 2);
+}
+
+/// Send an HTTP request to addr, returning the status code and body.
+fn http(addr: &str, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let code = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (code, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn serve_http() {
+    let mut t = Tracker::new("sled");
+    t.put("/home/me/repo", "main", "2 M");
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("127.0.0.1:{}", port);
+    let socket = t.db().join("daemon.sock");
+    t.serve(&["serve", "--http", &addr], &socket);
+    for _ in 0..100 {
+        if std::net::TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let (code, status) = http(&addr, "GET", "/status/home/me/repo", "");
+    assert_eq!(code, 200);
+    assert_eq!(status["branch"], "main");
+    assert_eq!(status["git_status"]["M"], 2);
+    assert_eq!(http(&addr, "GET", "/status/nowhere", "").0, 404);
+
+    let body = r#"{"branch": "dev", "git_status": {"??": 1}}"#;
+    let (code, status) = http(&addr, "PUT", "/status/tmp/new%20repo", body);
+    assert_eq!(code, 200);
+    assert_eq!(status["path"], "/tmp/new repo");
+    assert_eq!(status["categories"]["untracked"], 1);
+    assert_eq!(http(&addr, "PUT", "/status/tmp/bad", "[]").0, 400);
+
+    let (_, statuses) = http(&addr, "GET", "/statuses", "");
+    assert_eq!(statuses.as_array().unwrap().len(), 2);
+    let (_, summary) = http(&addr, "GET", "/summary", "");
+    assert_eq!(summary["repositories"], 2);
+    assert_eq!(summary["changes"], 3);
+    assert_eq!(http(&addr, "GET", "/elsewhere", "").0, 404);
+    // The socket is served too.
+    assert_eq!(t.field("/tmp/new repo", "branch"), "dev");
+}
+
+#[test]
+fn events_over_websocket() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+
+    let mut t = Tracker::new("sled");
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("127.0.0.1:{}", port);
+    t.serve(&["serve", "--http", &addr], &t.db().join("daemon.sock"));
+    let mut stream = (0..100)
+        .find_map(|_| {
+            TcpStream::connect(&addr)
+                .map_err(|_| thread::sleep(Duration::from_millis(20)))
+                .ok()
+        })
+        .unwrap();
+    write!(
+        stream,
+        "GET /events HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        addr
+    )
+    .unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        reader.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(head.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), "{}", head);
+
+    let mut next = || {
+        let mut header = [0; 2];
+        reader.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let len = match header[1] {
+            126 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len).unwrap();
+                u16::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload).unwrap();
+        serde_json::from_slice::<serde_json::Value>(&payload).unwrap()
+    };
+    t.put("/repo", "main", "1 M");
+    let event = next();
+    assert_eq!(event["event"], "changed");
+    assert_eq!(event["status"]["path"], "/repo");
+    t.ok(&["delete", "--path", "/repo"]);
+    let event = next();
+    assert_eq!(event["event"], "removed");
+    assert_eq!(event["path"], "/repo");
+
+    // Closing is answered straight away rather than with the next event.
+    reader.get_mut().write_all(b"\x88\x80abcd").unwrap();
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"\x88\x00");
+}
+
+#[test]
+fn get_ancestor() {
+    let t = Tracker::new("json");
+    t.put("/repo", "main", "1 M");
+    t.put("/repo/sub", "dev", "");
+    let get = |path: &str| t.ok(&["get", "--path", path, "--ancestor", "--field", "path"]);
+    assert_eq!(get("/repo/src/deep"), "/repo\n");
+    assert_eq!(get("/repo/sub/src/"), "/repo/sub\n");
+    assert_eq!(get("/repo"), "/repo\n");
+    assert_eq!(t.fail(&["get", "--path", "/repo/src"]).0, 2);
+    let (code, stderr) = t.fail(&["get", "--path", "/else/src", "--ancestor"]);
+    assert_eq!(code, 2);
+    assert_eq!(stderr, "no status found for /else/src\n");
+}
+
+#[test]
+fn put_porcelain_stdin() {
+    let t = Tracker::new("json");
+    t.ok_with(
+        &[
+            "put",
+            "--path",
+            "/p",
+            "--branch",
+            "main",
+            "--porcelain-stdin",
+        ],
+        " M a\nM  b\nMM c\n?? d\nR  e -> f\n",
+    );
+    assert_eq!(t.field("/p", "git-status"), "1 ?? | 2 M | 1 MM | 1 R");
+    assert_eq!(
+        t.ok(&[
+            "get",
+            "--path",
+            "/p",
+            "--format",
+            "{staged}/{unstaged} {renamed}R"
+        ]),
+        "3/2 1R\n"
+    );
+    t.ok_with(
+        &[
+            "put",
+            "--path",
+            "/p",
+            "--branch",
+            "main",
+            "--porcelain-stdin",
+        ],
+        "",
+    );
+    assert_eq!(t.field("/p", "git-status"), "");
+}
+
+#[test]
+fn put_auto() {
+    let t = Tracker::new("json");
+    let repo = t.home.join("repo");
+    repository(&repo);
+    fs::write(repo.join("a"), "changed").unwrap();
+    let path = repo.to_str().unwrap();
+    t.ok(&["put", "--path", path, "--auto"]);
+    assert_eq!(t.field(path, "branch"), "main");
+    assert_eq!(t.field(path, "git-status"), "1 ?? | 1 M");
+    assert!(t.field(path, "commit").ends_with(" a"));
+
+    let (code, stderr) = t.fail(&["put", "--path", t.home.to_str().unwrap(), "--auto"]);
+    assert_eq!(code, 3);
+    assert!(stderr.contains("not a git repository"), "{}", stderr);
+    assert_eq!(
+        t.fail(&["put", "--path", path, "--auto", "--branch", "x"])
+            .0,
+        3
+    );
+}
+
+#[test]
+fn put_merge() {
+    let t = Tracker::new("json");
+    t.ok(&[
+        "put",
+        "--path",
+        "/m",
+        "--branch",
+        "main",
+        "--git-status",
+        "1 M",
+        "--ahead",
+        "2",
+        "--upstream",
+        "origin/main",
+    ]);
+    t.ok(&["put", "--merge", "--path", "/m", "--stashes", "3"]);
+    let fields = |path: &str| {
+        ["branch", "git-status", "ahead", "stashes", "upstream"]
+            .map(|field| t.field(path, field))
+            .join(",")
+    };
+    assert_eq!(fields("/m"), "main,1 M,2,3,origin/main");
+    t.ok(&["put", "--merge", "--path", "/new", "--branch", "dev"]);
+    assert_eq!(fields("/new"), "dev,,0,0,");
+    // Without --merge, what isn't given is cleared.
+    t.ok(&["put", "--path", "/m", "--stashes", "1"]);
+    assert_eq!(fields("/m"), ",,0,1,");
+    assert_eq!(t.fail(&["put", "--merge", "--path", "/m", "--auto"]).0, 3);
+}
+
+#[test]
+fn metadata() {
+    let t = Tracker::new("json");
+    t.ok(&[
+        "put", "--path", "/k", "--branch", "main", "--meta", "owner=me", "--meta", "note=a=b",
+    ]);
+    let meta = |key: &str| t.ok(&["get", "--path", "/k", "--meta", key]);
+    assert_eq!(meta("owner"), "me\n");
+    assert_eq!(meta("note"), "a=b\n");
+    assert_eq!(meta("none"), "\n");
+    // Metadata is kept across puts and added to.
+    t.ok(&["put", "--path", "/k", "--branch", "dev", "--meta", "team=x"]);
+    assert_eq!(
+        t.ok(&["list"]),
+        "/k: dev {} {\"note\": \"a=b\", \"owner\": \"me\", \"team\": \"x\"}\n"
+    );
+    assert_eq!(
+        t.ok(&["get", "--path", "/k", "--format", "{meta:team}"]),
+        "x\n"
+    );
+    let (code, stderr) = t.fail(&["put", "--path", "/k", "--meta", "=x"]);
+    assert_eq!(code, 3);
+    assert!(
+        stderr.contains("invalid metadata =x, expected key=value"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn put_raw_status() {
+    let t = Tracker::new("json");
+    let raw = " M a\nUU b\n?? c\n?? d";
+    t.ok(&[
+        "put",
+        "--path",
+        "/r",
+        "--branch",
+        "main",
+        "--raw-status",
+        raw,
+    ]);
+    assert_eq!(t.field("/r", "git-status"), "2 ?? | 1 M | 1 UU");
+    assert_eq!(
+        t.ok(&[
+            "get",
+            "--path",
+            "/r",
+            "--format",
+            "{conflicted} {modified} {untracked}"
+        ]),
+        "1 1 2\n"
+    );
+    let both = [
+        "put",
+        "--path",
+        "/r",
+        "--raw-status",
+        raw,
+        "--git-status",
+        "1 M",
+    ];
+    assert_eq!(t.fail(&both).0, 3);
+}
+
+#[test]
+fn group_by_repo() {
+    let t = Tracker::new("json");
+    let (repo, worktree) = (t.home.join("repo"), t.home.join("worktree"));
+    repository(&repo);
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(&repo)
+        .args(["worktree", "add", "-q", "-b", "topic"])
+        .arg(&worktree)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let (repo, worktree) = (repo.to_str().unwrap(), worktree.to_str().unwrap());
+    t.ok(&["put", "--path", repo, "--auto"]);
+    t.ok(&["put", "--path", worktree, "--auto"]);
+    // A status without a repo_id is a group of its own.
+    let other = t.home.join("other");
+    let other = other.to_str().unwrap();
+    t.put(other, "main", "");
+    assert_eq!(t.field(worktree, "repo_id"), repo);
+    assert_eq!(
+        t.ok(&["list", "--group-by-repo"]),
+        format!(
+            "{}\n  {}: main {{}}\n{}\n  {}: main {{\"??\": 1}}\n  {}: topic {{}}\n",
+            other, other, repo, repo, worktree
+        )
+    );
+}
+
+#[test]
+fn list_remote() {
+    let t = Tracker::new("json");
+    let repo = t.home.join("repo");
+    repository(&repo);
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(&repo)
+        .args(["remote", "add", "origin", "git@github.com:myorg/app.git"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let path = repo.to_str().unwrap();
+    t.ok(&["put", "--path", path, "--auto"]);
+    t.put("/local", "main", "");
+    assert_eq!(
+        t.field(path, "remote"),
+        "origin git@github.com:myorg/app.git"
+    );
+    let paths = |remote: &str| {
+        t.ok(&["list", "--remote", remote])
+            .lines()
+            .map(|line| line.split(':').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(paths("github.com/myorg"), [path]);
+    assert_eq!(paths("https://github.com/myorg/"), [path]);
+    assert!(paths("gitlab.com").is_empty());
+}
+
+#[test]
+fn include_ignored() {
+    let t = Tracker::new("json");
+    let repo = t.home.join("repo");
+    repository(&repo);
+    fs::write(repo.join(".gitignore"), "build/\n").unwrap();
+    fs::create_dir(repo.join("build")).unwrap();
+    fs::write(repo.join("build/out"), "").unwrap();
+    let path = repo.to_str().unwrap();
+    t.ok(&["put", "--path", path, "--auto"]);
+    assert_eq!(t.field(path, "git-status"), "2 ??");
+    t.ok(&["put", "--path", path, "--auto", "--include-ignored"]);
+    assert_eq!(t.field(path, "git-status"), "1 !! | 2 ??");
+    assert_eq!(
+        t.ok(&["get", "--path", path, "--format", "{ignored}"]),
+        "1\n"
+    );
+    t.ok(&["refresh", "--all"]);
+    assert_eq!(t.field(path, "git-status"), "2 ??");
+    t.ok(&["refresh", "--all", "--include-ignored"]);
+    assert_eq!(t.field(path, "git-status"), "1 !! | 2 ??");
+    assert_eq!(t.fail(&["put", "--path", path, "--include-ignored"]).0, 3);
+}
+
+#[test]
+fn porcelain_lines() {
+    let t = Tracker::new("json");
+    t.put("/q", "main", "1 a=b|2 M");
+    let line = t.ok(&["get", "--path", "/q", "--porcelain"]);
+    let fields = line.trim_end().split('\t').collect::<Vec<_>>();
+    assert_eq!(fields.len(), 9, "{}", line);
+    assert_eq!(fields[..7], ["1", "/q", "main", "", "0", "0", "0"]);
+    assert_eq!(fields[8], "M=2 a%3Db=1");
+    assert_eq!(t.ok(&["list", "--porcelain=1"]), line);
+    assert_eq!(t.fail(&["list", "--porcelain=2"]).0, 3);
+}
+
+#[test]
+fn nul_separated() {
+    let t = Tracker::new("json");
+    t.put("/a", "main", "");
+    t.put("/b c", "dev", "1 M");
+    assert_eq!(
+        t.ok(&["get", "-0", "--path", "/a", "--path", "/b c", "--field", "branch"]),
+        "/a\0main\0/b c\0dev\0"
+    );
+    assert_eq!(t.ok(&["list", "-0"]), "/a: main {}\0/b c: dev {\"M\": 1}\0");
+    assert_eq!(t.fail(&["list", "-0", "--json"]).0, 3);
+}
+
+#[test]
+fn memory_forgets() {
+    let t = Tracker::new("memory");
+    t.put("/repo", "main", "");
+    assert_eq!(t.fail(&["get", "--path", "/repo"]).0, 2);
+}
+
+#[test]
+fn completions_and_init() {
+    let t = Tracker::new("sled");
+    assert!(t
+        .ok(&["completions", "bash"])
+        .contains("git-status-tracker"));
+    assert!(t.ok(&["init", "bash"]).contains("--porcelain-v2-stdin"));
+}
+
+#[test]
+fn migrate_old_layouts() {
+    let t = Tracker::new("json");
+    fs::create_dir_all(t.db()).unwrap();
+    fs::write(
+        t.db().join("db.json"),
+        r#"{"statuses": {"%05%00%00%00%00%00%00%00/repo": {"path": "/repo", "branch": "main", "git_status": {"M": 2}}}}"#,
+    )
+    .unwrap();
+    assert_eq!(t.field("/repo", "branch"), "main");
+    assert_eq!(
+        t.ok(&["migrate", "--dry-run"]),
+        "would migrate 1 statuses and 0 history entries to layout 3\n"
+    );
+    assert_eq!(
+        t.ok(&["migrate"]),
+        "migrated 1 statuses and 0 history entries to layout 3\n"
+    );
+    assert_eq!(
+        t.ok(&["migrate"]),
+        "migrated 0 statuses and 0 history entries to layout 3\n"
+    );
+    let file = fs::read_to_string(t.db().join("db.json")).unwrap();
+    assert!(file.contains(r#""version": 3"#), "{}", file);
+    assert!(file.contains(r#""unstaged": 2"#), "{}", file);
+    assert!(file.contains(r#""/repo": {"#), "{}", file);
+}
+
+#[test]
+fn profiles() {
+    let t = Tracker::new("sled");
+    t.put("/home", "main", "");
+    t.ok(&[
+        "--profile",
+        "work",
+        "put",
+        "--path",
+        "/work",
+        "--branch",
+        "dev",
+    ]);
+    assert!(t.ok(&["list"]).starts_with("/home:"));
+    assert_eq!(t.ok(&["list"]).lines().count(), 1);
+    let work = t.ok(&["--profile", "work", "list"]);
+    assert!(work.starts_with("/work: dev"), "{}", work);
+    assert_eq!(work.lines().count(), 1);
+    assert_eq!(t.ok(&["profiles", "list"]), "* default\n  work\n");
+    assert_eq!(
+        t.ok(&["--profile", "work", "profiles", "list"]),
+        "  default\n* work\n"
+    );
+    assert_eq!(t.fail(&["--profile", "../work", "list"]).0, 3);
+}